@@ -0,0 +1,203 @@
+use crate::error::Result;
+use crate::format::Format;
+use std::path::{Component, Path};
+
+/// A url to save in a batch, with optional overrides of the output directory and format of the batch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BatchItem {
+    pub url: String,
+    /// A directory below the output directory of the batch, which cannot be absolute or contain '..'.
+    pub out_dir: Option<String>,
+    pub format: Option<Format>,
+}
+
+impl BatchItem {
+    pub fn new(url: impl Into<String>) -> Self {
+        BatchItem {
+            url: url.into(),
+            out_dir: None,
+            format: None,
+        }
+    }
+
+    pub fn with_out_dir(mut self, out_dir: impl Into<String>) -> Self {
+        self.out_dir = Some(out_dir.into());
+        self
+    }
+
+    pub fn with_format(mut self, format: Format) -> Self {
+        self.format = Some(format);
+        self
+    }
+}
+
+impl From<String> for BatchItem {
+    fn from(url: String) -> Self {
+        Self::new(url)
+    }
+}
+
+impl From<&str> for BatchItem {
+    fn from(url: &str) -> Self {
+        Self::new(url)
+    }
+}
+
+/// Check that out_dir is a relative path that stays below the directory it is joined to.
+pub(crate) fn verify_out_dir(out_dir: &str) -> Result<()> {
+    if !Path::new(out_dir)
+        .components()
+        .all(|x| matches!(x, Component::Normal(_)))
+    {
+        return Err(format!(
+            "The directory '{}' must be a relative path without '..'.",
+            out_dir
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Split line into words on whitespace, where double quotes group words with spaces.
+/// A # that starts a word outside quotes starts a comment, which runs to the end of the line.
+fn split_words(line: &str) -> Result<Vec<String>> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut in_word = false;
+    for ch in line.chars() {
+        match ch {
+            '"' => {
+                quoted = !quoted;
+                in_word = true;
+            }
+            '#' if !quoted && !in_word => break,
+            x if x.is_whitespace() && !quoted => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            x => {
+                word.push(x);
+                in_word = true;
+            }
+        }
+    }
+    if quoted {
+        return Err("Unclosed '\"'.".into());
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Parse a line of a url list, which is None if it is blank or a comment.
+fn parse_line(line: &str) -> Result<Option<BatchItem>> {
+    let mut words = split_words(line)?.into_iter();
+    let mut item = match words.next() {
+        Some(x) => BatchItem::new(x),
+        None => return Ok(None),
+    };
+    for word in words {
+        match word.split_once('=') {
+            Some(("dir", x)) if !x.is_empty() => {
+                verify_out_dir(x)?;
+                item.out_dir = Some(x.to_owned())
+            }
+            Some(("format", x)) if !x.is_empty() => item.format = Some(Format::new(x)),
+            _ => {
+                return Err(format!(
+                    "Unknown option '{}', expected dir=<directory> or format=<format>.",
+                    word
+                )
+                .into())
+            }
+        }
+    }
+    Ok(Some(item))
+}
+
+/// Parse a url list, which has a url per line followed by optional dir=<directory> and format=<format> overrides.
+/// Values with spaces are written in double quotes, as dir="My shows".
+/// Blank lines and lines starting with # are ignored, as is anything after a # that follows whitespace outside quotes.
+///
+/// ```text
+/// # Weekly shows
+/// https://www.dr.dk/drtv/serie/bonderoeven_12345
+/// https://www.dr.dk/drtv/episode/tv-avisen_67890 dir=news format=mkv # Only this one
+/// ```
+pub fn parse_url_list(text: &str) -> Result<Vec<BatchItem>> {
+    let mut items = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let item = parse_line(line).map_err(|e| format!("Line {}: {}", i + 1, e))?;
+        items.extend(item);
+    }
+    Ok(items)
+}
+
+/// Read the url list at path, see parse_url_list for its syntax.
+pub async fn read_url_list(path: impl AsRef<Path>) -> Result<Vec<BatchItem>> {
+    let text = tokio::fs::read_to_string(path).await?;
+    parse_url_list(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHOW: &str = "https://www.dr.dk/drtv/serie/bonderoeven_12345";
+    const EPISODE: &str = "https://www.dr.dk/drtv/episode/tv-avisen_67890";
+
+    #[test]
+    fn parse_url_list_reads_items_and_options() {
+        let text = format!(
+            "# Weekly shows\n\n{}\n  {} dir=news format=mkv # Only this one\n{}\tdir=\"My shows/Show #1\"\n",
+            SHOW, EPISODE, SHOW
+        );
+        let items = parse_url_list(&text).unwrap();
+        assert_eq!(
+            items,
+            [
+                BatchItem::new(SHOW),
+                BatchItem::new(EPISODE)
+                    .with_out_dir("news")
+                    .with_format(Format::Mkv),
+                BatchItem::new(SHOW).with_out_dir("My shows/Show #1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_url_list_keeps_hashes_inside_words() {
+        let url = "https://www.dr.dk/drtv/episode/tv-avisen_67890#start";
+        let items = parse_url_list(&format!("{} dir=a#b #comment", url)).unwrap();
+        assert_eq!(items, [BatchItem::new(url).with_out_dir("a#b")]);
+    }
+
+    #[test]
+    fn parse_url_list_reports_the_line_of_errors() {
+        for (line, error) in [
+            ("size=big", "Unknown option"),
+            ("dir=", "Unknown option"),
+            ("dir=\"My shows", "Unclosed"),
+            ("dir=../shows", "relative path"),
+            ("dir=/shows", "relative path"),
+        ] {
+            let text = format!("{}\n{} {}\n", SHOW, EPISODE, line);
+            let err = parse_url_list(&text).unwrap_err().to_string();
+            assert!(err.starts_with("Line 2: "), "{}", err);
+            assert!(err.contains(error), "{}", err);
+        }
+    }
+
+    #[test]
+    fn verify_out_dir_only_allows_relative_directories() {
+        assert!(verify_out_dir("shows/news").is_ok());
+        assert!(verify_out_dir("..").is_err());
+        assert!(verify_out_dir("shows/../../x").is_err());
+        assert!(verify_out_dir("./shows").is_err());
+        assert!(verify_out_dir("/shows").is_err());
+    }
+}
@@ -0,0 +1,309 @@
+use crate::batch::BatchItem;
+use crate::converter::Converter;
+use crate::downloader::EpisodeCollection;
+use crate::error::Result;
+use crate::event::Event;
+use crate::filename::FilenameOptions;
+use crate::format::Format;
+use crate::job::JobHandle;
+use crate::models::episode::{EpisodeInfo, EpisodeMetadata, EpisodeStream};
+use crate::models::DownloadEvent;
+use crate::report::SaveReport;
+use crate::saver::OverwritePolicy;
+use crate::template::PathTemplate;
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Owns the runtime the blocking types run on.
+struct Runtime(Option<tokio::runtime::Runtime>);
+
+impl Runtime {
+    fn new() -> Result<Arc<Self>> {
+        Self::check_context()?;
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        Ok(Arc::new(Runtime(Some(rt))))
+    }
+
+    /// Blocking inside an async runtime would stall or panic it, so it is refused.
+    fn check_context() -> Result<()> {
+        match tokio::runtime::Handle::try_current() {
+            Ok(_) => Err(
+                "The blocking API cannot be used inside an async runtime, use the async types instead."
+                    .into(),
+            ),
+            Err(_) => Ok(()),
+        }
+    }
+
+    fn block_on<T>(&self, future: impl Future<Output = Result<T>>) -> Result<T> {
+        Self::check_context()?;
+        let rt = self
+            .0
+            .as_ref()
+            .expect("The runtime is only taken when dropped.");
+        rt.block_on(future)
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        // Dropping a runtime normally panics inside another runtime, which the last clone may be dropped in.
+        if let Some(rt) = self.0.take() {
+            rt.shutdown_background();
+        }
+    }
+}
+
+/// A blocking Requester, for programs that do not use async. It runs on a runtime of its own,
+/// which its clones and the Downloader and Saver made from it share.
+/// The blocking types return an error when called from inside an async runtime, where the async types should be used instead.
+#[derive(Clone)]
+pub struct Requester {
+    inner: crate::requester::Requester,
+    rt: Arc<Runtime>,
+}
+
+impl Requester {
+    /// Create a new Requester with a runtime of its own.
+    pub fn new() -> Result<Self> {
+        let rt = Runtime::new()?;
+        let inner = rt.block_on(crate::requester::Requester::new())?;
+        Ok(Requester { inner, rt })
+    }
+
+    /// Get the async Requester this wraps.
+    pub fn get_async(&self) -> &crate::requester::Requester {
+        &self.inner
+    }
+
+    /// Get EpisodeInfo from url.
+    pub fn get_episode_info(&self, url: &str) -> Result<EpisodeInfo> {
+        self.rt.block_on(self.inner.get_episode_info(url))
+    }
+
+    pub fn get_episode_metadata(&self, ep_id: &str) -> Result<EpisodeMetadata> {
+        self.rt.block_on(self.inner.get_episode_metadata(ep_id))
+    }
+
+    pub fn get_episode_details(&self, url: &str) -> Result<EpisodeInfo> {
+        self.rt.block_on(self.inner.get_episode_details(url))
+    }
+
+    /// Get a Vec of episode data urls from url.
+    pub fn get_show_episodes(&self, show_url: &str) -> Result<Vec<String>> {
+        self.rt.block_on(self.inner.get_show_episodes(show_url))
+    }
+
+    /// Get data url for episode with id ep_id.
+    pub fn get_episode_url(&self, ep_id: &str) -> Result<String> {
+        self.rt.block_on(self.inner.get_episode_url(ep_id))
+    }
+
+    pub fn get_episode_stream(&self, ep_id: &str) -> Result<EpisodeStream> {
+        self.rt.block_on(self.inner.get_episode_stream(ep_id))
+    }
+}
+
+/// A blocking Downloader.
+#[derive(Clone)]
+pub struct Downloader {
+    inner: crate::downloader::Downloader,
+    requester: Requester,
+}
+
+impl Downloader {
+    /// Create a new Downloader with a Requester and runtime of its own.
+    pub fn new() -> Result<Self> {
+        Ok(Self::with_requester(Requester::new()?))
+    }
+
+    /// Create a new Downloader that shares the runtime of requester.
+    pub fn with_requester(requester: Requester) -> Self {
+        Downloader {
+            inner: crate::downloader::Downloader::new(requester.inner.clone()),
+            requester,
+        }
+    }
+
+    /// Get the async Downloader this wraps.
+    pub fn get_async(&self) -> &crate::downloader::Downloader {
+        &self.inner
+    }
+
+    pub fn get_requester(&self) -> &Requester {
+        &self.requester
+    }
+
+    /// Get the download events of this Downloader. Handlers are called on the threads of the runtime.
+    pub fn events(&self) -> &Event<DownloadEvent> {
+        &self.inner.events
+    }
+
+    /// Download media from url to a Vec of optional EpisodeData.
+    pub fn download(&self, url: impl AsRef<str>) -> Result<EpisodeCollection> {
+        self.requester.rt.block_on(self.inner.download(url))
+    }
+
+    /// Download media from url to a Vec of optional EpisodeData, with the job controlled through handle from another thread.
+    pub fn download_with_handle(
+        &self,
+        url: impl AsRef<str>,
+        handle: &JobHandle,
+    ) -> Result<EpisodeCollection> {
+        self.requester
+            .rt
+            .block_on(self.inner.download_with_handle(url, handle))
+    }
+
+    /// Download media from url along with a report of how each episode went, see the async download_with_report.
+    pub fn download_with_report(
+        &self,
+        url: impl AsRef<str>,
+        handle: &JobHandle,
+    ) -> Result<(EpisodeCollection, SaveReport)> {
+        self.requester
+            .rt
+            .block_on(self.inner.download_with_report(url, handle))
+    }
+}
+
+/// A blocking Saver. See the async Saver for what its options do.
+#[derive(Clone)]
+pub struct Saver {
+    inner: crate::saver::Saver,
+    downloader: Downloader,
+}
+
+impl Saver {
+    pub fn new(downloader: Downloader) -> Self {
+        Saver {
+            inner: crate::saver::Saver::new(downloader.inner.clone()),
+            downloader,
+        }
+    }
+
+    fn map(mut self, f: impl FnOnce(crate::saver::Saver) -> crate::saver::Saver) -> Self {
+        self.inner = f(self.inner);
+        self
+    }
+
+    fn block_on<T>(&self, future: impl Future<Output = Result<T>>) -> Result<T> {
+        self.downloader.requester.rt.block_on(future)
+    }
+
+    /// Get the async Saver this wraps.
+    pub fn get_async(&self) -> &crate::saver::Saver {
+        &self.inner
+    }
+
+    pub fn get_downloader(&self) -> &Downloader {
+        &self.downloader
+    }
+
+    pub fn with_converter(self, converter: Converter) -> Self {
+        self.map(|x| x.with_converter(converter))
+    }
+
+    /// Find FFMPEG and use it as the Converter, see Converter::discover.
+    pub fn with_discovered_converter(self) -> Result<Self> {
+        let converter = self.block_on(Converter::discover())?;
+        Ok(self.with_converter(converter))
+    }
+
+    pub fn keep_partial_on_cancel(self, keep: bool) -> Self {
+        self.map(|x| x.keep_partial_on_cancel(keep))
+    }
+
+    pub fn audio_only(self, audio_only: bool) -> Self {
+        self.map(|x| x.audio_only(audio_only))
+    }
+
+    pub fn embed_subtitles(self, embed: bool) -> Self {
+        self.map(|x| x.embed_subtitles(embed))
+    }
+
+    pub fn embed_cover_art(self, embed: bool) -> Self {
+        self.map(|x| x.embed_cover_art(embed))
+    }
+
+    pub fn with_template(self, template: PathTemplate) -> Self {
+        self.map(|x| x.with_template(template))
+    }
+
+    pub fn media_server_layout(self, enabled: bool) -> Self {
+        self.map(|x| x.media_server_layout(enabled))
+    }
+
+    pub fn write_info_json(self, enabled: bool) -> Self {
+        self.map(|x| x.write_info_json(enabled))
+    }
+
+    pub fn with_overwrite_policy(self, policy: OverwritePolicy) -> Self {
+        self.map(|x| x.with_overwrite_policy(policy))
+    }
+
+    pub fn with_filename_options(self, options: FilenameOptions) -> Self {
+        self.map(|x| x.with_filename_options(options))
+    }
+
+    pub fn max_concurrent_episodes(self, max: usize) -> Self {
+        self.map(|x| x.max_concurrent_episodes(max))
+    }
+
+    /// Download media to file in directory, see the async save.
+    pub fn save(
+        &self,
+        url: impl Into<String>,
+        out_dir: impl AsRef<str>,
+        format: Option<Format>,
+    ) -> Result<SaveReport> {
+        self.block_on(self.inner.save(url, out_dir, format))
+    }
+
+    /// Download media to file in directory, with the job controlled through handle from another thread.
+    pub fn save_with_handle(
+        &self,
+        url: impl Into<String>,
+        out_dir: impl AsRef<str>,
+        format: Option<Format>,
+        handle: &JobHandle,
+    ) -> Result<SaveReport> {
+        self.block_on(self.inner.save_with_handle(url, out_dir, format, handle))
+    }
+
+    /// Save the episodes of report that failed or were cancelled again.
+    pub fn retry_failed(
+        &self,
+        report: &SaveReport,
+        out_dir: impl AsRef<str>,
+        format: Option<Format>,
+        handle: &JobHandle,
+    ) -> Result<SaveReport> {
+        self.block_on(self.inner.retry_failed(report, out_dir, format, handle))
+    }
+
+    /// Save every item of a batch, see the async save_batch.
+    pub fn save_batch<T: Into<BatchItem>>(
+        &self,
+        items: impl IntoIterator<Item = T>,
+        out_dir: impl AsRef<str>,
+        format: Option<Format>,
+        handle: &JobHandle,
+    ) -> Result<SaveReport> {
+        self.block_on(self.inner.save_batch(items, out_dir, format, handle))
+    }
+
+    /// Save every url in the url list at path.
+    pub fn save_batch_file(
+        &self,
+        path: impl AsRef<Path>,
+        out_dir: impl AsRef<str>,
+        format: Option<Format>,
+        handle: &JobHandle,
+    ) -> Result<SaveReport> {
+        self.block_on(self.inner.save_batch_file(path, out_dir, format, handle))
+    }
+}
@@ -1,56 +1,364 @@
-use crate::error::Result;
-use crate::event::Event;
-use std::borrow::Cow;
-use std::process::{Command, Stdio};
+use crate::error::{Cancelled, FfmpegError, Result};
+use crate::ffmpeg::{self, FfmpegCapabilities};
+use crate::format::Format;
+use crate::job::JobHandle;
+use crate::models::SubtitleTrack;
+use crate::profile::{AudioCodec, ConversionProfile, SourceCodecs, VideoCodec};
+use crate::progress::{parse_log_duration, ConvertProgress};
+use crate::resume;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::{Child, Command};
+use tracing::Instrument;
+
+/// How many lines of FFMPEG output to keep for errors.
+const LOG_TAIL_LINES: usize = 20;
+
+const PROTOCOL_WHITELIST: &str = "file,http,https,tcp,tls,crypto,pipe";
+
+/// An image to embed as the cover of the output.
+#[derive(Clone, Debug)]
+pub struct CoverArt {
+    /// Where the image is read from, a url or a path.
+    pub path: String,
+    /// Such as "image/jpeg".
+    pub mime_type: String,
+}
+
+/// Inputs of a single conversion besides the media itself.
+#[derive(Clone, Debug, Default)]
+pub struct ConvertOptions {
+    /// Duration of the input, used to report the percentage converted. Read from FFMPEG if not given.
+    pub duration: Option<Duration>,
+    /// Metadata tags written to the output, as FFMPEG tag names and values.
+    pub tags: Vec<(String, String)>,
+    /// Subtitle tracks muxed into the output, in order. Left out if the format cannot hold subtitles.
+    pub subtitles: Vec<SubtitleTrack>,
+    /// Left out if the format cannot hold cover art.
+    pub cover: Option<CoverArt>,
+    /// Codecs of the input, which copied streams keep. Defaults to the H.264 and AAC of DR streams.
+    pub source: SourceCodecs,
+}
 
 #[derive(Clone)]
-pub struct Converter<'a> {
-    ffmpeg_path: String,
-    pub on_convert: Event<'a, Cow<'a, str>>,
-    pub on_done: Event<'a, Cow<'a, str>>,
+pub struct Converter {
+    ffmpeg_path: PathBuf,
+    capabilities: Option<FfmpegCapabilities>,
+    profile: ConversionProfile,
 }
 
-impl<'a> Converter<'a> {
+impl Converter {
     /// Attempt to create a new Converter.
+    /// FFMPEG is not checked until it is used, see discover for finding and checking it up front.
     pub fn new(ffmpeg_path: String) -> Self {
         Converter {
+            ffmpeg_path: ffmpeg_path.into(),
+            capabilities: None,
+            profile: ConversionProfile::new(),
+        }
+    }
+
+    /// Find FFMPEG and check that it can be used.
+    /// FFMPEG is looked for in the FFMPEG_PATH environment variable, next to the executable, in PATH and in common install locations.
+    pub async fn discover() -> Result<Self> {
+        Self::discover_with(None).await
+    }
+
+    /// Like discover, but looks for FFMPEG at configured_path first.
+    pub async fn discover_with(configured_path: Option<PathBuf>) -> Result<Self> {
+        let ffmpeg_path = ffmpeg::locate(configured_path)?;
+        let capabilities = ffmpeg::probe(&ffmpeg_path).await?;
+        capabilities.verify()?;
+        tracing::info!(
+            path = %ffmpeg_path.display(),
+            version = %capabilities.version.name,
+            "found FFmpeg"
+        );
+        Ok(Converter {
             ffmpeg_path,
-            on_convert: Event::new(),
-            on_done: Event::new(),
-        }
-    }
-
-    /// Convert data to another format through FFMPEG.
-    pub fn convert(&self, input_url: impl AsRef<str>, out_path: impl AsRef<str>) -> Result<()> {
-        let out_path = out_path.as_ref();
-        std::fs::File::create(out_path)?; // Create file first otherwise canonicalize wont work.
-        let out_path = std::fs::canonicalize(out_path)?;
-        let out_path = out_path.to_str().ok_or("Invalid output path.")?;
-        self.on_convert.call(Cow::Owned(out_path.to_owned()));
-        let mut proc = Command::new(&self.ffmpeg_path)
-            .args(&[
-                "-y",
-                "-hide_banner",
-                "-loglevel",
-                "info",
-                "-protocol_whitelist",
-                "http,https,tcp,tls,crypto,pipe",
-                "-i",
-                input_url.as_ref(),
-                "-c",
-                "copy",
-                out_path,
-            ])
-            .stdin(Stdio::inherit())
-            .stderr(Stdio::inherit())
-            .stdout(Stdio::inherit())
-            .spawn()
-            .map_err(|_| {
-                "Could not start FFmpeg. Please install and copy to downloader root, or add to PATH."
-                    .to_owned()
-            })?;
-        proc.wait()?;
-        self.on_done.call(Cow::Owned(out_path.to_owned()));
+            capabilities: Some(capabilities),
+            profile: ConversionProfile::new(),
+        })
+    }
+
+    /// Set how the output is encoded. Defaults to copying the streams without re-encoding.
+    pub fn with_profile(mut self, profile: ConversionProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn get_profile(&self) -> &ConversionProfile {
+        &self.profile
+    }
+
+    /// Get what FFMPEG supports, if the Converter was created through discover.
+    pub fn get_capabilities(&self) -> Option<&FfmpegCapabilities> {
+        self.capabilities.as_ref()
+    }
+
+    /// Check that format can hold the output of the profile of the Converter, and that FFMPEG can write it.
+    /// FFMPEG itself is not checked if the capabilities are unknown.
+    pub fn verify_format(&self, format: &Format) -> Result<()> {
+        self.profile.verify(self.capabilities.as_ref())?;
+        format.verify_profile(&self.profile)?;
+        match &self.capabilities {
+            Some(x) if !x.has_muxer(format.get_muxer()) => Err(format!(
+                "FFmpeg {} cannot write {} files.",
+                x.version.name,
+                format.get_muxer()
+            )
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn push_input(args: &mut Vec<String>, input: &str) {
+        args.extend(["-protocol_whitelist", PROTOCOL_WHITELIST, "-i", input].map(str::to_owned));
+    }
+
+    /// Get the FFMPEG arguments for converting input into out_path.
+    fn build_args(
+        &self,
+        input: &str,
+        out_path: &str,
+        format: &Format,
+        options: &ConvertOptions,
+    ) -> Vec<String> {
+        let mut args = [
+            "-y",
+            "-nostdin",
+            "-hide_banner",
+            "-loglevel",
+            "info",
+            "-nostats",
+            "-progress",
+            "pipe:1",
+        ]
+        .map(str::to_owned)
+        .to_vec();
+        let has_video = self.profile.get_video_codec() != VideoCodec::Disabled;
+        let subtitle_codec = format.get_subtitle_codec().filter(|_| has_video);
+        let subtitles = match subtitle_codec {
+            Some(_) => options.subtitles.as_slice(),
+            None => &[],
+        };
+        let cover = options
+            .cover
+            .as_ref()
+            .filter(|_| format.supports_cover_art());
+        // Matroska keeps cover art as an attachment rather than a video stream.
+        let attach_cover = *format == Format::Mkv;
+
+        Self::push_input(&mut args, input);
+        for sub in subtitles {
+            Self::push_input(&mut args, &sub.url);
+        }
+        if let Some(cover) = cover.filter(|_| !attach_cover) {
+            Self::push_input(&mut args, &cover.path);
+        }
+
+        if has_video {
+            args.extend(["-map", "0:v?"].map(str::to_owned));
+        }
+        if self.profile.get_audio_codec() != AudioCodec::Disabled {
+            args.extend(["-map", "0:a?"].map(str::to_owned));
+        }
+        for i in 0..subtitles.len() {
+            args.extend(["-map".to_owned(), format!("{}:s:0", i + 1)]);
+        }
+        if cover.is_some() && !attach_cover {
+            args.extend(["-map".to_owned(), format!("{}:v:0", subtitles.len() + 1)]);
+        }
+
+        args.extend(self.profile.to_args());
+        args.extend(
+            options
+                .tags
+                .iter()
+                .flat_map(|(key, value)| ["-metadata".to_owned(), format!("{}={}", key, value)]),
+        );
+        for (i, sub) in subtitles.iter().enumerate() {
+            args.extend([
+                format!("-c:s:{}", i),
+                subtitle_codec.unwrap_or("copy").to_owned(),
+            ]);
+            if let Some(language) = &sub.language {
+                args.extend([
+                    format!("-metadata:s:s:{}", i),
+                    format!("language={}", language),
+                ]);
+            }
+            if let Some(name) = &sub.name {
+                args.extend([format!("-metadata:s:s:{}", i), format!("title={}", name)]);
+            }
+            let disposition = match (sub.default, sub.forced) {
+                (true, true) => "default+forced",
+                (true, false) => "default",
+                (false, true) => "forced",
+                (false, false) => "0",
+            };
+            args.extend([format!("-disposition:s:{}", i), disposition.to_owned()]);
+        }
+        match cover {
+            Some(cover) if attach_cover => args.extend([
+                "-attach".to_owned(),
+                cover.path.clone(),
+                "-metadata:s:t".to_owned(),
+                format!("mimetype={}", cover.mime_type),
+                "-metadata:s:t".to_owned(),
+                "filename=cover".to_owned(),
+            ]),
+            Some(cover) => {
+                // The cover comes after the video stream of the input, if it is kept.
+                let index = has_video as usize;
+                let codec = match cover.mime_type.as_str() {
+                    "image/jpeg" | "image/png" => "copy",
+                    _ => "mjpeg",
+                };
+                args.extend([
+                    format!("-c:v:{}", index),
+                    codec.to_owned(),
+                    format!("-disposition:v:{}", index),
+                    "attached_pic".to_owned(),
+                ]);
+            }
+            None => (),
+        }
+        args.extend(["-f", format.get_muxer(), out_path].map(str::to_owned));
+        args
+    }
+
+    /// Read FFMPEG -progress output. Duration is the duration of the input in microseconds, or 0 if not yet known.
+    async fn read_progress(
+        output: impl AsyncRead + Unpin,
+        duration: &AtomicU64,
+        on_progress: impl Fn(&ConvertProgress),
+    ) {
+        let mut progress = ConvertProgress::default();
+        let mut lines = BufReader::new(output).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if progress.duration.is_none() {
+                progress.duration = match duration.load(Ordering::Relaxed) {
+                    0 => None,
+                    us => Some(Duration::from_micros(us)),
+                };
+            }
+            if progress.apply_line(&line) {
+                on_progress(&progress);
+            }
+        }
+    }
+
+    /// Read FFMPEG log output, storing the input duration in duration if it is not yet known.
+    async fn read_log(output: impl AsyncRead + Unpin, duration: &AtomicU64) -> Vec<String> {
+        let mut log = VecDeque::with_capacity(LOG_TAIL_LINES);
+        let mut lines = BufReader::new(output).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            tracing::trace!(target: "ffmpeg", "{}", line);
+            if let Some(input_duration) = parse_log_duration(&line) {
+                let us = input_duration.as_micros() as u64;
+                duration
+                    .compare_exchange(0, us, Ordering::Relaxed, Ordering::Relaxed)
+                    .ok();
+            }
+            if log.len() == LOG_TAIL_LINES {
+                log.pop_front();
+            }
+            log.push_back(line);
+        }
+        log.into()
+    }
+
+    async fn wait(proc: &mut Child, handle: &JobHandle) -> Result<ExitStatus> {
+        tokio::select! {
+            status = proc.wait() => Ok(status?),
+            _ = handle.cancelled() => {
+                proc.kill().await.ok();
+                Err(Cancelled.into())
+            }
+        }
+    }
+
+    /// Convert data to another format through FFMPEG, calling on_progress as FFMPEG reports progress.
+    /// The tags, subtitles and cover art of options are written whether the streams are re-encoded or only remuxed.
+    /// Returns an error without starting FFMPEG if format cannot hold the output of the profile from the codecs of options.source.
+    /// If the job is cancelled through handle, FFMPEG is killed and Cancelled is returned.
+    /// If FFMPEG fails, an FfmpegError with its exit code and last lines of output is returned.
+    /// The output is written to a part file next to out_path, which replaces out_path only once FFMPEG succeeds.
+    pub async fn convert(
+        &self,
+        input_url: impl AsRef<str>,
+        out_path: impl AsRef<str>,
+        format: &Format,
+        options: &ConvertOptions,
+        handle: &JobHandle,
+        on_progress: impl Fn(&ConvertProgress),
+    ) -> Result<()> {
+        format.verify_profile_for(&self.profile, &options.source)?;
+        let out_path = Path::new(out_path.as_ref());
+        let part_path = resume::part_path(out_path);
+        tokio::fs::File::create(&part_path).await?; // Create file first otherwise canonicalize wont work.
+        let part_path = tokio::fs::canonicalize(part_path).await?;
+        let part_str = part_path.to_str().ok_or("Invalid output path.")?;
+        let span = tracing::info_span!("convert", input = input_url.as_ref(), out_path = part_str);
+        let result = async move {
+            let mut proc = Command::new(&self.ffmpeg_path)
+                .args(self.build_args(input_url.as_ref(), part_str, format, options))
+                .stdin(Stdio::null())
+                .stderr(Stdio::piped())
+                .stdout(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn()
+                .map_err(|e| {
+                    format!(
+                        "Could not start FFmpeg at {}: {}. Use Converter::discover to find an installed FFmpeg.",
+                        self.ffmpeg_path.display(),
+                        e
+                    )
+                })?;
+            let output = proc
+                .stdout
+                .take()
+                .ok_or("Could not capture FFmpeg output.")?;
+            let log = proc
+                .stderr
+                .take()
+                .ok_or("Could not capture FFmpeg log.")?;
+
+            let duration = AtomicU64::new(options.duration.map(|x| x.as_micros() as u64).unwrap_or(0));
+            let start = std::time::Instant::now();
+            let (status, _, log) = tokio::join!(
+                Self::wait(&mut proc, handle),
+                Self::read_progress(output, &duration, on_progress),
+                Self::read_log(log, &duration)
+            );
+            let status = status?;
+            if !status.success() {
+                let err = FfmpegError {
+                    code: status.code(),
+                    log,
+                };
+                tracing::warn!(code = ?err.code, "conversion failed");
+                return Err(err.into());
+            }
+            tracing::info!(
+                elapsed_ms = start.elapsed().as_millis() as u64,
+                "conversion finished"
+            );
+            Ok(())
+        }
+        .instrument(span)
+        .await;
+        if let Err(e) = result {
+            // FFMPEG output cannot be resumed, so the part file is of no use.
+            tokio::fs::remove_file(&part_path).await.ok();
+            return Err(e);
+        }
+        tokio::fs::rename(&part_path, out_path).await?;
         Ok(())
     }
 }
@@ -1,11 +1,23 @@
-use crate::error::Result;
+use crate::error::{Cancelled, Result};
 use crate::event::Event;
-use crate::models::{episode::EpisodeData, URLType};
+use crate::hls::{self, StreamSelection};
+use crate::http;
+use crate::job::JobHandle;
+use crate::models::episode::{EpisodeData, EpisodeInfo};
+use crate::models::{DownloadEvent, URLType, VariantInfo};
+use crate::progress::ProgressTracker;
+use crate::report::{EpisodeOutcome, EpisodeReport, SaveReport};
 use crate::requester::Requester;
+use crate::resume::ResumeState;
 use crate::util::remove_newline;
-use rayon::prelude::*;
+use futures::{Stream, StreamExt};
 use reqwest::StatusCode;
-use std::borrow::Cow;
+use std::error::Error;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::broadcast;
+use tracing::Instrument;
 
 lazy_static! {
     static ref DR_EP_URL_REGEX: regex::Regex =
@@ -14,33 +26,31 @@ lazy_static! {
 
 pub type EpisodeCollection = Vec<Option<EpisodeData>>;
 
-#[derive(Clone)]
-pub struct Downloader<'a> {
-    requester: Requester,
-    pub download_event: Event<'a, Cow<'a, str>>,
-    pub finished_event: Event<'a, Cow<'a, str>>,
-    pub failed_event: Event<'a, Cow<'a, str>>,
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+/// How many episodes of a show are downloaded at once.
+const SHOW_CONCURRENCY: usize = 4;
+
+/// What download_stream downloaded.
+pub(crate) struct StreamDownload {
+    pub duration: Duration,
+    pub variant: VariantInfo,
 }
 
-impl<'a> Default for Downloader<'a> {
-    /// Create a default Downloader.
-    fn default() -> Self {
-        let rt = tokio::runtime::Handle::current();
-        let requester = rt
-            .block_on(Requester::new())
-            .expect("Could not wait for requester future. Try using Downloader::new() manually.");
-        Self::new(requester)
-    }
+#[derive(Clone)]
+pub struct Downloader {
+    requester: Requester,
+    /// Handlers of the events of this Downloader and its clones.
+    pub events: Event<DownloadEvent>,
+    channel: broadcast::Sender<DownloadEvent>,
 }
 
-impl<'a> Downloader<'a> {
+impl Downloader {
     /// Create a new Downloader.
     pub fn new(requester: Requester) -> Self {
         Downloader {
             requester,
-            download_event: Event::new(),
-            finished_event: Event::new(),
-            failed_event: Event::new(),
+            events: Event::new(),
+            channel: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
         }
     }
 
@@ -48,10 +58,54 @@ impl<'a> Downloader<'a> {
         &self.requester
     }
 
-    pub async fn default_async() -> Result<Downloader<'a>> {
+    /// Create a Downloader with a new Requester. See the blocking module for use outside an async runtime.
+    pub async fn default_async() -> Result<Downloader> {
         Ok(Self::new(Requester::new().await?))
     }
 
+    /// Get a stream of the events of this Downloader and its clones.
+    /// Events are dropped for streams that fall too far behind.
+    pub fn event_stream(&self) -> impl Stream<Item = DownloadEvent> + Send + 'static {
+        futures::stream::unfold(self.channel.subscribe(), |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(event) => return Some((event, rx)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+
+    /// Call subscribers of events and send to event streams.
+    pub(crate) fn emit(&self, event: DownloadEvent) {
+        // Not the whole event, as the stream url holds tokens.
+        tracing::trace!(kind = event.kind(), episode = %event.episode().id, "download event");
+        self.events.call(event.clone());
+        // Sending only fails when no stream is listening.
+        self.channel.send(event).ok();
+    }
+
+    /// Report a failed or cancelled episode, and pass the error on.
+    pub(crate) fn report_error(
+        &self,
+        episode: &EpisodeInfo,
+        err: Box<dyn Error + Send + Sync>,
+    ) -> Box<dyn Error + Send + Sync> {
+        let episode = episode.clone();
+        if crate::error::is_cancelled(err.as_ref()) {
+            tracing::info!("episode cancelled");
+            self.emit(DownloadEvent::Cancelled { episode });
+            return err;
+        }
+        tracing::warn!(error = %err, "episode failed");
+        self.emit(DownloadEvent::Failed {
+            episode,
+            error: err.to_string(),
+        });
+        err
+    }
+
     async fn verify_url(url: &str) -> Result<()> {
         if !DR_EP_URL_REGEX.is_match(url) {
             return Err("Unrecognzed URL.".into());
@@ -59,8 +113,9 @@ impl<'a> Downloader<'a> {
         Ok(())
     }
 
-    async fn get_as_string(url: &str) -> Result<String> {
-        let result = reqwest::get(url).await?;
+    async fn get_as_string(&self, url: &str) -> Result<String> {
+        let client = self.requester.get_client();
+        let result = http::send(client, client.get(url)).await?;
         let status = result.status();
         if status != StatusCode::OK {
             if status == StatusCode::UNAUTHORIZED {
@@ -68,44 +123,240 @@ impl<'a> Downloader<'a> {
             }
             return Err(format!("Status code was not 200 OK.\nCode: {}", status).into());
         }
-        let text = result.text().await?;
+        let text = result.text().await.map_err(|e| e.without_url())?;
         Ok(text)
     }
 
-    pub(crate) async fn download_episode(&self, ep_url: String) -> Result<EpisodeData> {
-        self.download_event.call(Cow::Owned(ep_url.clone()));
-        let info = self.requester.get_episode_info(&ep_url).await?;
+    pub(crate) async fn get_as_bytes(&self, url: &str) -> Result<Vec<u8>> {
+        let client = self.requester.get_client();
+        let result = http::send(client, client.get(url)).await?;
+        let status = result.status();
+        if status != StatusCode::OK {
+            return Err(format!("Status code was not 200 OK.\nCode: {}", status).into());
+        }
+        Ok(result.bytes().await.map_err(|e| e.without_url())?.to_vec())
+    }
+
+    /// Get the data of a segment, requesting only its byte range if it has one.
+    async fn get_segment(&self, segment: &hls::Segment) -> Result<Vec<u8>> {
+        let range = match segment.byte_range {
+            Some(x) => x,
+            None => return self.get_as_bytes(&segment.uri).await,
+        };
+        let client = self.requester.get_client();
+        let request = client
+            .get(&segment.uri)
+            .header(reqwest::header::RANGE, range.header_value());
+        let result = http::send(client, request).await?;
+        let status = result.status();
+        if status != StatusCode::PARTIAL_CONTENT && status != StatusCode::OK {
+            return Err(
+                format!("Status code was not 206 Partial Content.\nCode: {}", status).into(),
+            );
+        }
+        let data = result.bytes().await.map_err(|e| e.without_url())?;
+        let data = match status {
+            StatusCode::PARTIAL_CONTENT => &data[..],
+            // The server ignored the range and sent the whole resource.
+            _ => usize::try_from(range.offset)
+                .ok()
+                .zip(usize::try_from(range.offset + range.length).ok())
+                .and_then(|(start, end)| data.get(start..end))
+                .ok_or("Segment was shorter than its byte range.")?,
+        };
+        if data.len() as u64 != range.length {
+            return Err("Segment did not match its byte range.".into());
+        }
+        Ok(data.to_vec())
+    }
+
+    /// Get the media playlist of the selected stream of an HLS stream, and which stream it is.
+    pub(crate) async fn get_media_playlist(
+        &self,
+        stream_url: &str,
+        selection: StreamSelection,
+    ) -> Result<(hls::MediaPlaylist, VariantInfo)> {
+        let playlist = self.get_as_string(stream_url).await?;
+        if !hls::is_master(&playlist) {
+            let variant = VariantInfo {
+                uri: stream_url.to_owned(),
+                ..Default::default()
+            };
+            return Ok((hls::parse_media(stream_url, &playlist)?, variant));
+        }
+        let master = hls::parse_master(stream_url, &playlist)?;
+        let variant = master
+            .select(selection)
+            .ok_or("Master playlist contained no variants.")?;
+        let playlist = self.get_as_string(&variant.uri).await?;
+        Ok((hls::parse_media(&variant.uri, &playlist)?, variant))
+    }
+
+    /// Download the segments of an HLS stream to a part file, continuing from its resume state if there is one.
+    pub(crate) async fn download_stream(
+        &self,
+        episode: &EpisodeInfo,
+        stream_url: &str,
+        part_path: impl AsRef<Path>,
+        selection: StreamSelection,
+        handle: &JobHandle,
+    ) -> Result<StreamDownload> {
+        let part_path = part_path.as_ref();
+        let (media, variant) = self.get_media_playlist(stream_url, selection).await?;
+        let segment_count = media.segments.len();
+        let download = StreamDownload {
+            duration: Duration::try_from_secs_f64(media.duration())?,
+            variant,
+        };
+
+        // A part file of another episode or variant is started over rather than spliced onto.
+        let new_state = ResumeState::new(&episode.id, &download.variant.uri, segment_count);
+        let mut state = match ResumeState::load(part_path).await {
+            Some(x) if !x.continues(&new_state) => {
+                tracing::info!(path = %part_path.display(), "discarding resume state of another download");
+                new_state
+            }
+            // A part file that was removed or cut short would leave a gap in the output.
+            Some(x) if !x.matches_part_file(part_path).await => {
+                tracing::info!(path = %part_path.display(), "discarding resume state that does not match its part file");
+                new_state
+            }
+            Some(x) => x,
+            None => new_state,
+        };
+        if state.complete {
+            return Ok(download);
+        }
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(part_path)
+            .await?;
+        let mut offset = state.resume_offset();
+        file.set_len(offset).await?;
+        file.seek(std::io::SeekFrom::Start(offset)).await?;
+
+        let tracker = ProgressTracker::new(segment_count, offset);
+
+        for (i, segment) in media.segments.iter().enumerate().skip(state.next_segment()) {
+            handle.checkpoint().await?;
+            let data = tokio::select! {
+                data = self.get_segment(segment) => data?,
+                _ = handle.cancelled() => return Err(Cancelled.into()),
+            };
+            file.write_all(&data).await?;
+            file.sync_data().await?;
+            offset += data.len() as u64;
+            state.mark_done(i, offset);
+            state.save(part_path).await?;
+            self.emit(DownloadEvent::Progress {
+                episode: episode.clone(),
+                progress: tracker.update(offset, i + 1),
+            });
+        }
+        Ok(download)
+    }
+
+    pub(crate) async fn download_episode(
+        &self,
+        ep_url: String,
+        handle: &JobHandle,
+    ) -> Result<EpisodeData> {
+        handle.checkpoint().await?;
+        let info = self.requester.get_episode_details(&ep_url).await?;
+        self.download_details(info).await
+    }
+
+    async fn download_details(&self, info: EpisodeInfo) -> Result<EpisodeData> {
+        let span = tracing::info_span!("episode", id = %info.id, name = %info.name);
+        let data = self
+            .download_info(&info)
+            .instrument(span.clone())
+            .await
+            .map_err(|e| span.in_scope(|| self.report_error(&info, e)))?;
+        Ok(EpisodeData { info, data })
+    }
+
+    /// Download the episode at ep_url, recording how it went rather than returning an error.
+    async fn download_episode_report(
+        &self,
+        ep_url: String,
+        handle: &JobHandle,
+    ) -> (Option<EpisodeData>, EpisodeReport) {
+        let start = Instant::now();
+        let lookup = async {
+            handle.checkpoint().await?;
+            self.requester.get_episode_details(&ep_url).await
+        };
+        let info = match lookup.await {
+            Ok(x) => x,
+            Err(e) => {
+                tracing::warn!(url = %ep_url, error = %e, "could not look up episode");
+                let report = EpisodeReport {
+                    url: ep_url,
+                    episode: None,
+                    out_dir: None,
+                    format: None,
+                    outcome: EpisodeOutcome::from_error(e),
+                };
+                return (None, report);
+            }
+        };
+        let (data, outcome) = match self.download_details(info.clone()).await {
+            Ok(data) => {
+                let outcome = EpisodeOutcome::Saved {
+                    path: None,
+                    bytes: data.data.len() as u64,
+                    elapsed: start.elapsed(),
+                };
+                (Some(data), outcome)
+            }
+            Err(e) => (None, EpisodeOutcome::from_error(e)),
+        };
+        let report = EpisodeReport {
+            url: ep_url,
+            episode: Some(info),
+            out_dir: None,
+            format: None,
+            outcome,
+        };
+        (data, report)
+    }
+
+    async fn download_info(&self, info: &EpisodeInfo) -> Result<Vec<u8>> {
         let url = self.requester.get_episode_url(&info.id).await?;
-        let content = Self::get_as_string(&url).await?;
-        self.finished_event.call(Cow::Owned(ep_url));
-        Ok(EpisodeData {
-            info,
-            data: content.as_bytes().into(),
-        })
+        self.emit(DownloadEvent::Resolved {
+            episode: info.clone(),
+            stream_url: url.clone(),
+        });
+        self.emit(DownloadEvent::Started {
+            episode: info.clone(),
+        });
+        let content = self.get_as_string(&url).await?;
+        self.emit(DownloadEvent::Finished {
+            episode: info.clone(),
+            path: None,
+            bytes: content.len() as u64,
+        });
+        Ok(content.into_bytes())
     }
 
-    pub(crate) async fn download_show(&self, show_url: String) -> Result<EpisodeCollection> {
-        self.download_event.call(Cow::Owned(show_url.clone()));
+    /// Download every episode of the show at show_url, up to SHOW_CONCURRENCY at once, continuing past episodes that fail.
+    pub(crate) async fn download_show(
+        &self,
+        show_url: String,
+        handle: &JobHandle,
+    ) -> Result<(EpisodeCollection, SaveReport)> {
         let eps = self.requester.get_show_episodes(&show_url).await?;
-        let rt = tokio::runtime::Handle::current();
-        let show_data = eps
-            .into_par_iter()
-            .map(|ep| {
-                let url_copy = ep.clone();
-                let result = rt.block_on(self.download_episode(ep));
-                match result {
-                    Ok(data) => {
-                        self.finished_event.call(Cow::Owned(url_copy));
-                        Some(data)
-                    }
-                    Err(_) => {
-                        self.failed_event.call(Cow::Owned(url_copy));
-                        None
-                    }
-                }
-            })
-            .collect::<EpisodeCollection>();
-        Ok(show_data)
+        let (show_data, reports): (EpisodeCollection, Vec<EpisodeReport>) =
+            futures::stream::iter(eps)
+                .map(|ep| self.download_episode_report(ep, handle))
+                .buffered(SHOW_CONCURRENCY)
+                .unzip()
+                .await;
+        Ok((show_data, SaveReport { episodes: reports }))
     }
 
     fn sanitize_url(mut url: &str) -> &str {
@@ -115,12 +366,272 @@ impl<'a> Downloader<'a> {
 
     /// Download media from url to a Vec of optional EpisodeData.
     pub async fn download(&self, url: impl AsRef<str>) -> Result<EpisodeCollection> {
+        self.download_with_handle(url, &JobHandle::new()).await
+    }
+
+    /// Download media from url to a Vec of optional EpisodeData, with the job controlled through handle.
+    pub async fn download_with_handle(
+        &self,
+        url: impl AsRef<str>,
+        handle: &JobHandle,
+    ) -> Result<EpisodeCollection> {
         let url = String::from(Self::sanitize_url(url.as_ref()));
-        Downloader::verify_url(&url).await?;
-        let url_type = URLType::get(&url)?;
-        match url_type {
-            URLType::Playlist => Ok(self.download_show(url).await?),
-            URLType::Video => Ok(vec![Some(self.download_episode(url).await?)]),
+        let span = tracing::info_span!("download", url = %url);
+        async move {
+            Downloader::verify_url(&url).await?;
+            let url_type = URLType::get(&url)?;
+            match url_type {
+                URLType::Playlist => {
+                    let (show_data, _) = self.download_show(url, handle).await?;
+                    if handle.is_cancelled() {
+                        return Err(Cancelled.into());
+                    }
+                    Ok(show_data)
+                }
+                URLType::Video => Ok(vec![Some(self.download_episode(url, handle).await?)]),
+            }
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Download media from url like download_with_handle, along with a report of how each episode went.
+    /// Episodes that fail or are cancelled are None in the collection and listed in the report,
+    /// and an error is only returned if the url could not be looked up.
+    pub async fn download_with_report(
+        &self,
+        url: impl AsRef<str>,
+        handle: &JobHandle,
+    ) -> Result<(EpisodeCollection, SaveReport)> {
+        let url = String::from(Self::sanitize_url(url.as_ref()));
+        let span = tracing::info_span!("download", url = %url);
+        async move {
+            Downloader::verify_url(&url).await?;
+            let url_type = URLType::get(&url)?;
+            match url_type {
+                URLType::Playlist => self.download_show(url, handle).await,
+                URLType::Video => {
+                    let (data, report) = self.download_episode_report(url, handle).await;
+                    Ok((
+                        vec![data],
+                        SaveReport {
+                            episodes: vec![report],
+                        },
+                    ))
+                }
+            }
+        }
+        .instrument(span)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::is_cancelled;
+    use crate::models::episode::EpisodeMetadata;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+    use tokio::sync::Semaphore;
+
+    const SEGMENTS: usize = 4;
+    const SEGMENT_LEN: usize = 10;
+
+    fn episode() -> EpisodeInfo {
+        EpisodeInfo {
+            name: "tv-avisen".to_owned(),
+            id: "67890".to_owned(),
+            metadata: EpisodeMetadata::default(),
         }
     }
+
+    fn segment(index: usize) -> Vec<u8> {
+        vec![index as u8; SEGMENT_LEN]
+    }
+
+    fn stream() -> Vec<u8> {
+        (0..SEGMENTS).flat_map(segment).collect()
+    }
+
+    /// Serve a media playlist of SEGMENTS segments, holding each segment back until a permit is added to gate.
+    /// Returns the url of the playlist.
+    async fn serve_stream(gate: Arc<Semaphore>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let gate = gate.clone();
+                tokio::spawn(async move {
+                    let mut request = vec![0; 4096];
+                    let read = socket.read(&mut request).await.unwrap_or(0);
+                    let request = String::from_utf8_lossy(&request[..read]);
+                    let path = request.split_whitespace().nth(1).unwrap_or_default();
+                    let index = path
+                        .strip_prefix("/segment")
+                        .and_then(|x| x.strip_suffix(".ts"))
+                        .and_then(|x| x.parse().ok());
+                    let body = match index {
+                        Some(index) => {
+                            gate.acquire().await.unwrap().forget();
+                            segment(index)
+                        }
+                        None => {
+                            let mut playlist = "#EXTM3U\n#EXT-X-TARGETDURATION:2\n".to_owned();
+                            for i in 0..SEGMENTS {
+                                playlist += &format!("#EXTINF:2.0,\nsegment{}.ts\n", i);
+                            }
+                            playlist += "#EXT-X-ENDLIST\n";
+                            playlist.into_bytes()
+                        }
+                    };
+                    let head = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        body.len()
+                    );
+                    socket.write_all(head.as_bytes()).await.ok();
+                    socket.write_all(&body).await.ok();
+                });
+            }
+        });
+        format!("http://{}/index.m3u8", addr)
+    }
+
+    /// Get a part file path of its own in the temporary directory, without the files a previous run left.
+    fn part_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "dr-downloader-{}-{}.ts.part",
+            name,
+            std::process::id()
+        ));
+        remove_part_file(&path);
+        path
+    }
+
+    fn remove_part_file(path: &Path) {
+        std::fs::remove_file(path).ok();
+        std::fs::remove_file(crate::resume::state_path(path)).ok();
+    }
+
+    /// Wait until condition holds, failing the test if it takes too long.
+    async fn wait_until(condition: impl Fn() -> bool) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !condition() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("condition was never met");
+    }
+
+    /// Count the segments done according to the progress events of downloader.
+    fn track_progress(downloader: &Downloader) -> Arc<AtomicUsize> {
+        let done = Arc::new(AtomicUsize::new(0));
+        let tracked = done.clone();
+        // Handlers stay subscribed after their Subscription is dropped.
+        let _ = downloader.events.sub(move |event| {
+            if let DownloadEvent::Progress { progress, .. } = event {
+                tracked.store(progress.segments_done, Ordering::SeqCst);
+            }
+        });
+        done
+    }
+
+    fn spawn_download(
+        downloader: &Downloader,
+        url: &str,
+        part: &Path,
+        handle: &JobHandle,
+    ) -> tokio::task::JoinHandle<Result<StreamDownload>> {
+        let (downloader, url, part, handle) = (
+            downloader.clone(),
+            url.to_owned(),
+            part.to_owned(),
+            handle.clone(),
+        );
+        tokio::spawn(async move {
+            downloader
+                .download_stream(&episode(), &url, &part, StreamSelection::Best, &handle)
+                .await
+        })
+    }
+
+    #[tokio::test]
+    async fn paused_download_stops_until_resumed() {
+        let gate = Arc::new(Semaphore::new(SEGMENTS));
+        let url = serve_stream(gate).await;
+        let part = part_file("download-pause");
+        let downloader = Downloader::new(Requester::new().await.unwrap());
+        let handle = JobHandle::new();
+        let done = track_progress(&downloader);
+        // Pausing from a handler takes effect before the next segment is requested.
+        let pauser = handle.clone();
+        let _ = downloader.events.sub(move |event| {
+            if let DownloadEvent::Progress { progress, .. } = event {
+                if progress.segments_done == 1 {
+                    pauser.pause();
+                }
+            }
+        });
+
+        let task = spawn_download(&downloader, &url, &part, &handle);
+        wait_until(|| done.load(Ordering::SeqCst) == 1).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(done.load(Ordering::SeqCst), 1);
+        assert!(!task.is_finished());
+
+        handle.resume();
+        task.await.unwrap().unwrap();
+        assert_eq!(done.load(Ordering::SeqCst), SEGMENTS);
+        assert_eq!(std::fs::read(&part).unwrap(), stream());
+        remove_part_file(&part);
+    }
+
+    #[tokio::test]
+    async fn cancelled_download_leaves_a_resumable_part_file() {
+        // Only the first two segments are served, the third is held back until the download is cancelled.
+        let gate = Arc::new(Semaphore::new(2));
+        let url = serve_stream(gate.clone()).await;
+        let part = part_file("download-cancel");
+        let downloader = Downloader::new(Requester::new().await.unwrap());
+        let done = track_progress(&downloader);
+
+        let handle = JobHandle::new();
+        let task = spawn_download(&downloader, &url, &part, &handle);
+        wait_until(|| done.load(Ordering::SeqCst) == 2).await;
+        handle.cancel();
+        let err = task.await.unwrap().err().unwrap();
+        assert!(is_cancelled(err.as_ref()));
+
+        let state = ResumeState::load(&part).await.unwrap();
+        assert_eq!(state.next_segment(), 2);
+        assert_eq!(state.resume_offset(), 2 * SEGMENT_LEN as u64);
+        assert!(state.matches_part_file(&part).await);
+
+        // The held back request takes one of the permits when it is released.
+        gate.add_permits(SEGMENTS);
+        let resumed = Arc::new(AtomicUsize::new(usize::MAX));
+        let first = resumed.clone();
+        let _ = downloader.events.sub(move |event| {
+            if let DownloadEvent::Progress { progress, .. } = event {
+                first
+                    .compare_exchange(
+                        usize::MAX,
+                        progress.segments_done,
+                        Ordering::SeqCst,
+                        Ordering::SeqCst,
+                    )
+                    .ok();
+            }
+        });
+        let task = spawn_download(&downloader, &url, &part, &JobHandle::new());
+        task.await.unwrap().unwrap();
+        // The download continued after the segments that were already done.
+        assert_eq!(resumed.load(Ordering::SeqCst), 3);
+        assert_eq!(std::fs::read(&part).unwrap(), stream());
+        remove_part_file(&part);
+    }
 }
@@ -0,0 +1,14 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// Returned when a job was cancelled through its JobHandle.
+#[derive(Debug, Clone, Copy)]
+pub struct Cancelled;
+
+impl Display for Cancelled {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("The job was cancelled.")
+    }
+}
+
+impl Error for Cancelled {}
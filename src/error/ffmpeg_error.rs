@@ -0,0 +1,26 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// Returned when FFMPEG exits unsuccessfully.
+#[derive(Debug, Clone)]
+pub struct FfmpegError {
+    /// The exit code, if FFMPEG was not terminated by a signal.
+    pub code: Option<i32>,
+    /// The last lines FFMPEG wrote to stderr.
+    pub log: Vec<String>,
+}
+
+impl Display for FfmpegError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "FFmpeg exited with code {}.", code)?,
+            None => f.write_str("FFmpeg was terminated.")?,
+        }
+        for line in &self.log {
+            write!(f, "\n{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for FfmpegError {}
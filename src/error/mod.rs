@@ -1,7 +1,16 @@
+pub mod cancelled;
+pub mod ffmpeg_error;
 pub mod generic_error;
 pub mod ok_or_generic;
 
+pub use cancelled::Cancelled;
+pub use ffmpeg_error::FfmpegError;
 pub use generic_error::GenericError;
 pub use ok_or_generic::OkOrGeneric;
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Returns true if the error was caused by a cancelled job.
+pub fn is_cancelled(err: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+    err.is::<Cancelled>()
+}
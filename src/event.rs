@@ -1,28 +1,142 @@
-#[derive(Clone)]
-pub struct Event<'a, A> {
-    subscribers: Vec<&'a (dyn Fn(&A) + Sync)>,
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+type Handler<A> = Arc<dyn Fn(&A) + Send + Sync>;
+type Subscribers<A> = Mutex<Vec<(usize, Handler<A>)>>;
+
+static NEXT_SUBSCRIPTION_ID: AtomicUsize = AtomicUsize::new(0);
+
+pub struct Event<A> {
+    subscribers: Arc<Subscribers<A>>,
+}
+
+/// A handle to a handler subscribed to an Event.
+/// Dropping it keeps the handler subscribed, call unsubscribe to remove it.
+pub struct Subscription {
+    unsubscribe: Box<dyn FnOnce() + Send + Sync>,
+}
+
+impl Subscription {
+    /// Remove the handler from the Event it was subscribed to.
+    pub fn unsubscribe(self) {
+        (self.unsubscribe)();
+    }
 }
 
-impl<'a, A> Event<'a, A> {
+impl<A: 'static> Event<A> {
     pub fn new() -> Self {
         Event {
-            subscribers: vec![],
+            subscribers: Arc::new(Mutex::new(vec![])),
         }
     }
 
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<(usize, Handler<A>)>> {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
     pub fn call(&self, arg: A) {
-        for f in &self.subscribers {
+        // Clone the handlers so they can unsubscribe while being called.
+        let handlers = self
+            .lock()
+            .iter()
+            .map(|(_, f)| f.clone())
+            .collect::<Vec<_>>();
+        for f in handlers {
             f(&arg);
         }
     }
 
-    pub fn sub(&mut self, handler: &'a (dyn Fn(&A) + Sync)) {
-        self.subscribers.push(handler);
+    pub fn sub(&self, handler: impl Fn(&A) + Send + Sync + 'static) -> Subscription {
+        let id = NEXT_SUBSCRIPTION_ID.fetch_add(1, Ordering::Relaxed);
+        self.lock().push((id, Arc::new(handler)));
+        let subscribers: Weak<Subscribers<A>> = Arc::downgrade(&self.subscribers);
+        Subscription {
+            unsubscribe: Box::new(move || {
+                if let Some(subscribers) = subscribers.upgrade() {
+                    let mut subscribers = subscribers
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner());
+                    subscribers.retain(|(x, _)| *x != id);
+                }
+            }),
+        }
+    }
+}
+
+impl<A: 'static> Clone for Event<A> {
+    /// Clones share their subscribers, so a handler subscribed through any of them is called by all of them.
+    fn clone(&self) -> Self {
+        Event {
+            subscribers: Arc::clone(&self.subscribers),
+        }
     }
 }
 
-impl<'a, A> Default for Event<'a, A> {
+impl<A: 'static> Default for Event<A> {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Subscribe a handler that counts its calls, returning the count.
+    fn counter(event: &Event<u32>) -> (Arc<AtomicUsize>, Subscription) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        let sub = event.sub(move |_| {
+            counted.fetch_add(1, Ordering::SeqCst);
+        });
+        (calls, sub)
+    }
+
+    #[test]
+    fn unsubscribed_handler_is_dropped_and_not_called() {
+        let event = Event::new();
+        let (calls, sub) = counter(&event);
+        let (other_calls, _other) = counter(&event);
+        event.call(1);
+        sub.unsubscribe();
+        event.call(2);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(other_calls.load(Ordering::SeqCst), 2);
+        // The Event no longer holds the handler, so what it captured is freed.
+        assert_eq!(Arc::strong_count(&calls), 1);
+    }
+
+    #[test]
+    fn dropped_subscription_keeps_handler() {
+        let event = Event::new();
+        let (calls, sub) = counter(&event);
+        drop(sub);
+        event.call(1);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn clones_share_subscribers() {
+        let event = Event::new();
+        let clone = event.clone();
+        let (calls, sub) = counter(&clone);
+        event.call(1);
+        clone.call(2);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        sub.unsubscribe();
+        event.call(3);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn unsubscribe_after_event_is_dropped() {
+        let event = Event::new();
+        let (calls, sub) = counter(&event);
+        drop(event);
+        // Dropping the Event dropped its handlers.
+        assert_eq!(Arc::strong_count(&calls), 1);
+        sub.unsubscribe();
+    }
+}
@@ -0,0 +1,257 @@
+use crate::error::Result;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command;
+
+/// Environment variable that can point to the FFMPEG executable.
+pub const FFMPEG_PATH_VAR: &str = "FFMPEG_PATH";
+
+/// The oldest major version with the -progress output the Converter relies on.
+const MIN_MAJOR_VERSION: u32 = 4;
+
+#[cfg(windows)]
+const EXECUTABLE_NAME: &str = "ffmpeg.exe";
+#[cfg(not(windows))]
+const EXECUTABLE_NAME: &str = "ffmpeg";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FfmpegVersion {
+    /// The full version string, such as "6.0-full_build-www.gyan.dev".
+    pub name: String,
+    pub major: Option<u32>,
+    pub minor: Option<u32>,
+}
+
+/// What an FFMPEG executable supports.
+#[derive(Clone, Debug)]
+pub struct FfmpegCapabilities {
+    pub version: FfmpegVersion,
+    pub muxers: HashSet<String>,
+    pub encoders: HashSet<String>,
+    pub input_protocols: HashSet<String>,
+}
+
+impl FfmpegCapabilities {
+    pub fn has_muxer(&self, muxer: &str) -> bool {
+        self.muxers.contains(muxer)
+    }
+
+    pub fn has_encoder(&self, encoder: &str) -> bool {
+        self.encoders.contains(encoder)
+    }
+
+    pub fn has_input_protocol(&self, protocol: &str) -> bool {
+        self.input_protocols.contains(protocol)
+    }
+
+    /// Check that this FFMPEG can be used by the Converter.
+    pub fn verify(&self) -> Result<()> {
+        match self.version.major {
+            Some(major) if major < MIN_MAJOR_VERSION => {
+                return Err(format!(
+                    "FFmpeg {} is too old, version {} or newer is required.",
+                    self.version.name, MIN_MAJOR_VERSION
+                )
+                .into())
+            }
+            _ => (),
+        }
+        if !self.has_input_protocol("file") {
+            return Err("FFmpeg does not support reading files.".into());
+        }
+        Ok(())
+    }
+}
+
+/// Get the paths FFMPEG is looked for in, in order of preference.
+fn candidates(configured: Option<PathBuf>) -> Vec<PathBuf> {
+    let mut candidates = vec![];
+    candidates.extend(configured);
+    if let Some(path) = std::env::var_os(FFMPEG_PATH_VAR) {
+        candidates.push(path.into());
+    }
+    if let Some(dir) = std::env::current_exe()
+        .ok()
+        .and_then(|x| x.parent().map(Path::to_owned))
+    {
+        candidates.push(dir.join(EXECUTABLE_NAME));
+    }
+    if let Some(paths) = std::env::var_os("PATH") {
+        candidates.extend(std::env::split_paths(&paths).map(|x| x.join(EXECUTABLE_NAME)));
+    }
+    candidates.extend(common_locations());
+    candidates
+}
+
+#[cfg(windows)]
+fn common_locations() -> Vec<PathBuf> {
+    let mut locations = vec![
+        PathBuf::from(r"C:\ffmpeg\bin\ffmpeg.exe"),
+        PathBuf::from(r"C:\ProgramData\chocolatey\bin\ffmpeg.exe"),
+    ];
+    let under = |var: &str, path: &str| std::env::var_os(var).map(|x| PathBuf::from(x).join(path));
+    locations.extend(under("ProgramFiles", r"ffmpeg\bin\ffmpeg.exe"));
+    locations.extend(under("LOCALAPPDATA", r"Microsoft\WinGet\Links\ffmpeg.exe"));
+    locations.extend(under("USERPROFILE", r"scoop\shims\ffmpeg.exe"));
+    locations
+}
+
+#[cfg(not(windows))]
+fn common_locations() -> Vec<PathBuf> {
+    [
+        "/usr/bin",
+        "/usr/local/bin",
+        "/opt/homebrew/bin",
+        "/snap/bin",
+    ]
+    .iter()
+    .map(|x| Path::new(x).join(EXECUTABLE_NAME))
+    .collect()
+}
+
+/// Find the first FFMPEG executable, preferring the configured path.
+pub fn locate(configured: Option<PathBuf>) -> Result<PathBuf> {
+    candidates(configured)
+        .into_iter()
+        .find(|x| x.is_file())
+        .ok_or_else(|| {
+            format!(
+                "Could not find FFmpeg. Please install it and add it to PATH, or set {} to its path.",
+                FFMPEG_PATH_VAR
+            )
+            .into()
+        })
+}
+
+async fn run(ffmpeg_path: &Path, arg: &str) -> Result<String> {
+    let output = Command::new(ffmpeg_path)
+        .args(["-hide_banner", arg])
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| format!("Could not start FFmpeg at {}: {}", ffmpeg_path.display(), e))?;
+    if !output.status.success() {
+        return Err(format!("FFmpeg {} failed with {}.", arg, output.status).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn parse_version(output: &str) -> Result<FfmpegVersion> {
+    let name = output
+        .lines()
+        .next()
+        .and_then(|x| x.strip_prefix("ffmpeg version "))
+        .and_then(|x| x.split_whitespace().next())
+        .ok_or("Could not parse FFmpeg version.")?;
+    // Git builds are versioned like "N-111004-g4893cbcaba", so the numbers are optional.
+    // Releases built from git tags are versioned like "n6.1.1".
+    let mut numbers = name
+        .strip_prefix('n')
+        .unwrap_or(name)
+        .split(|x: char| !x.is_ascii_digit())
+        .map(|x| x.parse::<u32>().ok());
+    let major = numbers.next().flatten().filter(|_| !name.starts_with('N'));
+    let minor = major.and(numbers.next().flatten());
+    Ok(FfmpegVersion {
+        name: name.to_owned(),
+        major,
+        minor,
+    })
+}
+
+/// Parse the names in -muxers or -encoders output, which come after a column of flags.
+fn parse_flagged_names(output: &str) -> HashSet<String> {
+    output
+        .lines()
+        .skip_while(|x| !x.trim_start().starts_with("--"))
+        .skip(1)
+        .filter_map(|x| x.split_whitespace().nth(1))
+        // Muxers can have several comma separated names.
+        .flat_map(|x| x.split(','))
+        .map(str::to_owned)
+        .collect()
+}
+
+fn parse_input_protocols(output: &str) -> HashSet<String> {
+    output
+        .lines()
+        .skip_while(|x| x.trim() != "Input:")
+        .skip(1)
+        .take_while(|x| x.trim() != "Output:")
+        .map(|x| x.trim().to_owned())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+/// Run the FFMPEG at ffmpeg_path to find out what it supports.
+pub async fn probe(ffmpeg_path: impl AsRef<Path>) -> Result<FfmpegCapabilities> {
+    let ffmpeg_path = ffmpeg_path.as_ref();
+    let (version, muxers, encoders, protocols) = tokio::try_join!(
+        run(ffmpeg_path, "-version"),
+        run(ffmpeg_path, "-muxers"),
+        run(ffmpeg_path, "-encoders"),
+        run(ffmpeg_path, "-protocols"),
+    )?;
+    Ok(FfmpegCapabilities {
+        version: parse_version(&version)?,
+        muxers: parse_flagged_names(&muxers),
+        encoders: parse_flagged_names(&encoders),
+        input_protocols: parse_input_protocols(&protocols),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(line: &str) -> FfmpegVersion {
+        parse_version(&format!(
+            "{}\nbuilt with gcc 12.2.0 (Rev10, Built by MSYS2 project)\n",
+            line
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn parse_version_reads_release_versions() {
+        let v = version("ffmpeg version 6.0-full_build-www.gyan.dev Copyright (c) 2000-2023 the FFmpeg developers");
+        assert_eq!(v.name, "6.0-full_build-www.gyan.dev");
+        assert_eq!((v.major, v.minor), (Some(6), Some(0)));
+        let v = version(
+            "ffmpeg version 4.4.2-0ubuntu0.22.04.1 Copyright (c) 2000-2021 the FFmpeg developers",
+        );
+        assert_eq!((v.major, v.minor), (Some(4), Some(4)));
+        let v = version("ffmpeg version n6.1.1 Copyright (c) 2000-2023 the FFmpeg developers");
+        assert_eq!((v.major, v.minor), (Some(6), Some(1)));
+    }
+
+    #[test]
+    fn parse_version_leaves_git_builds_unnumbered() {
+        let v = version("ffmpeg version N-111004-g4893cbcaba-20230614 Copyright (c) 2000-2023 the FFmpeg developers");
+        assert_eq!(v.name, "N-111004-g4893cbcaba-20230614");
+        assert_eq!((v.major, v.minor), (None, None));
+    }
+
+    #[test]
+    fn parse_version_rejects_other_output() {
+        assert!(parse_version("").is_err());
+        assert!(parse_version("ffprobe version 6.0 Copyright (c) 2007-2023").is_err());
+    }
+
+    #[test]
+    fn parse_flagged_names_splits_muxer_names() {
+        let output = " D. = Demuxing supported
+ .E = Muxing supported
+ --
+  E mov             QuickTime / MOV
+  E mp4             MP4 (MPEG-4 Part 14)
+ DE matroska,webm   Matroska / WebM
+";
+        let names = parse_flagged_names(output);
+        for name in ["mov", "mp4", "matroska", "webm"] {
+            assert!(names.contains(name), "{}", name);
+        }
+        assert_eq!(names.len(), 4);
+    }
+}
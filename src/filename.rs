@@ -0,0 +1,256 @@
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Names Windows reserves for devices, with or without an extension.
+const WINDOWS_RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM0", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7",
+    "COM8", "COM9", "LPT0", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// The longest extension kept when a name is shortened.
+const MAX_KEPT_EXTENSION: usize = 16;
+
+/// The operating system whose filename rules are followed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TargetOs {
+    Windows,
+    MacOs,
+    /// Linux and other Unix like systems.
+    Unix,
+}
+
+impl TargetOs {
+    /// Get the operating system this is compiled for.
+    pub fn current() -> Self {
+        if cfg!(windows) {
+            TargetOs::Windows
+        } else if cfg!(target_os = "macos") {
+            TargetOs::MacOs
+        } else {
+            TargetOs::Unix
+        }
+    }
+
+    fn is_illegal(&self, ch: char) -> bool {
+        match self {
+            TargetOs::Windows => matches!(ch, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*'),
+            TargetOs::MacOs => matches!(ch, '/' | ':'),
+            TargetOs::Unix => ch == '/',
+        }
+    }
+}
+
+/// How names are made into legal filenames.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FilenameOptions {
+    /// Defaults to the operating system this is compiled for.
+    /// Use Windows when saving to a share that Windows machines read as well.
+    pub target: TargetOs,
+    /// The longest name in bytes. Defaults to 200, which leaves room for the suffixes of
+    /// part files and sidecars below the 255 byte limit of most filesystems.
+    pub max_bytes: usize,
+    /// Whether æ, ø and å are written as ae, oe and aa, accents are removed and any other non ASCII character is replaced. Defaults to false.
+    pub transliterate: bool,
+    /// The longest path in bytes a PathTemplate renders, relative to the output directory, which is not counted.
+    /// The filename is shortened to fit. Defaults to None, which leaves the length of paths uncapped.
+    /// Windows limits full paths to 259 characters unless long paths are enabled, so subtract the length
+    /// of the output directory and the 20 bytes part files and sidecars add to the name.
+    pub max_path_bytes: Option<usize>,
+}
+
+impl Default for FilenameOptions {
+    fn default() -> Self {
+        FilenameOptions {
+            target: TargetOs::current(),
+            max_bytes: 200,
+            transliterate: false,
+            max_path_bytes: None,
+        }
+    }
+}
+
+fn transliterate(name: &str) -> String {
+    let mut ascii = String::with_capacity(name.len());
+    for ch in name.chars() {
+        match ch {
+            'æ' => ascii.push_str("ae"),
+            'Æ' => ascii.push_str("Ae"),
+            'ø' => ascii.push_str("oe"),
+            'Ø' => ascii.push_str("Oe"),
+            'å' => ascii.push_str("aa"),
+            'Å' => ascii.push_str("Aa"),
+            x if x.is_ascii() => ascii.push(x),
+            // Decompose to strip accents, such as é to e.
+            x => {
+                for part in x.nfkd().filter(|x| !is_combining_mark(*x)) {
+                    ascii.push(if part.is_ascii() { part } else { '_' });
+                }
+            }
+        }
+    }
+    ascii
+}
+
+/// Cut name to at most max_bytes on a character boundary, keeping a short extension.
+fn truncate(name: &str, max_bytes: usize) -> String {
+    if name.len() <= max_bytes {
+        return name.to_owned();
+    }
+    let ext = name
+        .rfind('.')
+        .filter(|x| *x > 0 && name.len() - x <= MAX_KEPT_EXTENSION && name.len() - x < max_bytes)
+        .map(|x| &name[x..])
+        .unwrap_or_default();
+    let mut end = max_bytes - ext.len();
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    let stem = name[..end].trim_end();
+    format!("{}{}", stem, ext)
+}
+
+fn is_windows_reserved(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or_default().trim_end();
+    WINDOWS_RESERVED
+        .iter()
+        .any(|x| x.eq_ignore_ascii_case(stem))
+}
+
+/// Make name a legal filename with the default options.
+pub fn legalize_filename(name: impl AsRef<str>) -> String {
+    legalize_filename_with(name, &FilenameOptions::default())
+}
+
+/// Make name a legal filename for the target of options.
+/// The result is a single non empty path component that is never . or .., so it stays inside the directory it is joined to.
+pub fn legalize_filename_with(name: impl AsRef<str>, options: &FilenameOptions) -> String {
+    let mut name: String = name.as_ref().nfc().collect();
+    if options.transliterate {
+        name = transliterate(&name);
+    }
+    let mut name: String = name
+        .chars()
+        .filter_map(|x| match x {
+            '\t' | '\n' | '\r' => Some(' '),
+            x if x.is_control() || options.target.is_illegal(x) => None,
+            // Separators are illegal on every target, as the name is a single component.
+            '/' | '\\' => None,
+            x => Some(x),
+        })
+        .collect();
+    let trim = |name: &str| {
+        // Leading dots hide files, and make up the . and .. components.
+        let name = name.trim_start_matches(|x: char| x == '.' || x.is_whitespace());
+        match options.target {
+            TargetOs::Windows => name.trim_end_matches(|x: char| x == '.' || x.is_whitespace()),
+            _ => name.trim_end(),
+        }
+        .to_owned()
+    };
+    let max_bytes = options.max_bytes.max(1);
+    name = trim(&truncate(&trim(&name), max_bytes));
+    if name.is_empty() {
+        name.push('_');
+    }
+    // Checked last, as trimming and truncating can leave a reserved name such as "NUL ." as "NUL".
+    if options.target == TargetOs::Windows && is_windows_reserved(&name) {
+        name.insert(0, '_');
+        name = trim(&truncate(&name, max_bytes));
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(target: TargetOs) -> FilenameOptions {
+        FilenameOptions {
+            target,
+            ..Default::default()
+        }
+    }
+
+    fn legalize(name: &str, target: TargetOs) -> String {
+        legalize_filename_with(name, &options(target))
+    }
+
+    #[test]
+    fn removes_illegal_characters_per_target() {
+        let name = "A: \"B\" <C>?*|/\\\u{7}\tD";
+        assert_eq!(legalize(name, TargetOs::Windows), "A B C D");
+        assert_eq!(legalize(name, TargetOs::MacOs), "A \"B\" <C>?*| D");
+        assert_eq!(legalize(name, TargetOs::Unix), "A: \"B\" <C>?*| D");
+    }
+
+    #[test]
+    fn never_returns_dot_components_or_empty_names() {
+        for target in [TargetOs::Windows, TargetOs::MacOs, TargetOs::Unix] {
+            for name in ["", " ", ".", "..", "...", "/", "../.."] {
+                let legal = legalize(name, target);
+                assert!(
+                    !legal.is_empty() && legal != "." && legal != "..",
+                    "{:?}",
+                    legal
+                );
+            }
+        }
+        assert_eq!(legalize(".hidden", TargetOs::Unix), "hidden");
+        assert_eq!(legalize("name. ", TargetOs::Windows), "name");
+        assert_eq!(legalize("name. ", TargetOs::Unix), "name.");
+    }
+
+    #[test]
+    fn prefixes_windows_reserved_names() {
+        assert_eq!(legalize("CON", TargetOs::Windows), "_CON");
+        assert_eq!(legalize("nul.mp4", TargetOs::Windows), "_nul.mp4");
+        assert_eq!(legalize("Com1 .txt", TargetOs::Windows), "_Com1 .txt");
+        assert_eq!(legalize("CONSOLE", TargetOs::Windows), "CONSOLE");
+        assert_eq!(legalize("CON", TargetOs::Unix), "CON");
+        // Only reserved once the trailing dot and space are trimmed.
+        assert_eq!(legalize("NUL .", TargetOs::Windows), "_NUL");
+        let options = FilenameOptions {
+            max_bytes: 3,
+            ..options(TargetOs::Windows)
+        };
+        // Only reserved once truncated, and kept within max_bytes.
+        assert_eq!(legalize_filename_with("AUXILIARY", &options), "_AU");
+    }
+
+    #[test]
+    fn truncates_on_character_boundaries_keeping_the_extension() {
+        let options = FilenameOptions {
+            max_bytes: 10,
+            ..options(TargetOs::Unix)
+        };
+        assert_eq!(
+            legalize_filename_with("abcdefghijkl.mp4", &options),
+            "abcdef.mp4"
+        );
+        assert_eq!(legalize_filename_with("ææææææ.mp4", &options), "æææ.mp4");
+        let odd = FilenameOptions {
+            max_bytes: 9,
+            ..options.clone()
+        };
+        assert_eq!(legalize_filename_with("ææææææ.mp4", &odd), "ææ.mp4");
+        assert_eq!(
+            legalize_filename_with("abcdefghijkl", &options),
+            "abcdefghij"
+        );
+        assert!(legalize_filename_with("a".repeat(300), &FilenameOptions::default()).len() <= 200);
+    }
+
+    #[test]
+    fn normalizes_and_transliterates() {
+        // A decomposed å is composed, so the name does not depend on how it was typed.
+        assert_eq!(legalize("a\u{30a}", TargetOs::Unix), "å");
+        let options = FilenameOptions {
+            transliterate: true,
+            ..options(TargetOs::Unix)
+        };
+        assert_eq!(
+            legalize_filename_with("Ærø Å é 日本", &options),
+            "Aeroe Aa e __"
+        );
+    }
+}
@@ -1,29 +1,228 @@
-use std::borrow::Cow;
+use crate::error::{OkOrGeneric, Result};
+use crate::profile::{AudioCodec, ConversionProfile, SourceCodecs, VideoCodec};
+use serde_json::{json, Value};
+use std::fmt::{self, Display, Formatter};
 
-pub struct Format<'a> {
-    format_ext: Cow<'a, str>,
+/// The container media is saved in.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Format {
+    Mp4,
+    Mkv,
+    Mov,
+    /// MPEG transport stream, which is what DR streams in. It can be saved without a Converter, as can MP4.
+    Ts,
+    Webm,
+    M4a,
+    Mp3,
+    Ogg,
+    Opus,
+    /// A container this crate does not know, which is not validated.
+    Custom {
+        /// The file extension, with the leading dot.
+        extension: String,
+        /// The name of the FFMPEG muxer.
+        muxer: String,
+    },
 }
 
-impl<'a> Format<'a> {
+impl Format {
+    /// Get the format of a name or extension such as "mp4" or ".mkv".
+    /// Unknown formats become Custom, with the name as both extension and muxer.
     pub fn new(name_or_ext: impl AsRef<str>) -> Self {
-        let mut format = name_or_ext.as_ref().to_owned();
-        if !format.starts_with('.') {
-            format.insert(0, '.');
+        let name = name_or_ext.as_ref().trim_start_matches('.').to_lowercase();
+        match name.as_str() {
+            "mp4" => Format::Mp4,
+            "mkv" | "matroska" => Format::Mkv,
+            "mov" => Format::Mov,
+            "ts" | "mpegts" => Format::Ts,
+            "webm" => Format::Webm,
+            "m4a" => Format::M4a,
+            "mp3" => Format::Mp3,
+            "ogg" => Format::Ogg,
+            "opus" => Format::Opus,
+            _ => Self::custom(&name, &name),
         }
+    }
 
-        Self {
-            format_ext: Cow::Owned(format),
+    /// Construct a format this crate does not know, written by the FFMPEG muxer.
+    pub fn custom(extension: impl AsRef<str>, muxer: impl Into<String>) -> Self {
+        Format::Custom {
+            extension: format!(".{}", extension.as_ref().trim_start_matches('.')),
+            muxer: muxer.into(),
         }
     }
 
-    /// Construct format with EXACT format extension on comptime.
-    pub const fn from_exact_extension(ext: &'a str) -> Self {
-        Self {
-            format_ext: Cow::Borrowed(ext),
-        }
+    /// Get the format as JSON, with its extension and muxer.
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "extension": self.get_extension(),
+            "muxer": self.get_muxer(),
+        })
     }
 
+    /// Parse a format written by to_json.
+    /// Known formats are parsed back to their variant, rather than becoming Custom.
+    pub(crate) fn parse(json: &Value) -> Result<Self> {
+        let extension = json["extension"]
+            .as_str()
+            .ok_or_generic("Could not get the extension of a format.")?;
+        let muxer = json["muxer"]
+            .as_str()
+            .ok_or_generic("Could not get the muxer of a format.")?;
+        let known = Format::new(extension);
+        Ok(match known.get_muxer() == muxer {
+            true => known,
+            false => Format::custom(extension, muxer),
+        })
+    }
+
+    /// Get the file extension, with the leading dot.
     pub fn get_extension(&self) -> &str {
-        &self.format_ext
+        match self {
+            Format::Mp4 => ".mp4",
+            Format::Mkv => ".mkv",
+            Format::Mov => ".mov",
+            Format::Ts => ".ts",
+            Format::Webm => ".webm",
+            Format::M4a => ".m4a",
+            Format::Mp3 => ".mp3",
+            Format::Ogg => ".ogg",
+            Format::Opus => ".opus",
+            Format::Custom { extension, .. } => extension,
+        }
+    }
+
+    /// Get the name of the FFMPEG muxer for this format.
+    pub fn get_muxer(&self) -> &str {
+        match self {
+            Format::Mp4 => "mp4",
+            Format::Mkv => "matroska",
+            Format::Mov => "mov",
+            Format::Ts => "mpegts",
+            Format::Webm => "webm",
+            Format::M4a => "ipod",
+            Format::Mp3 => "mp3",
+            Format::Ogg => "ogg",
+            Format::Opus => "opus",
+            Format::Custom { muxer, .. } => muxer,
+        }
+    }
+
+    /// Whether the format only holds audio.
+    pub fn is_audio_only(&self) -> bool {
+        matches!(self, Format::M4a | Format::Mp3 | Format::Ogg | Format::Opus)
+    }
+
+    /// Get the FFMPEG subtitle encoder for this format, if it can hold subtitles.
+    pub fn get_subtitle_codec(&self) -> Option<&'static str> {
+        match self {
+            Format::Mp4 | Format::Mov => Some("mov_text"),
+            Format::Mkv => Some("srt"),
+            Format::Webm => Some("webvtt"),
+            _ => None,
+        }
+    }
+
+    /// Whether FFMPEG can embed cover art in this format.
+    pub fn supports_cover_art(&self) -> bool {
+        matches!(
+            self,
+            Format::Mp4 | Format::Mov | Format::M4a | Format::Mp3 | Format::Mkv
+        )
+    }
+
+    fn supports_video(&self, codec: VideoCodec) -> bool {
+        match self {
+            _ if codec == VideoCodec::Disabled => true,
+            Format::Mp4 | Format::Mkv | Format::Mov | Format::Custom { .. } => true,
+            Format::Ts => matches!(codec, VideoCodec::H264 | VideoCodec::H265),
+            Format::Webm => codec == VideoCodec::Av1,
+            Format::M4a | Format::Mp3 | Format::Ogg | Format::Opus => false,
+        }
+    }
+
+    fn supports_audio(&self, codec: AudioCodec) -> bool {
+        match self {
+            _ if codec == AudioCodec::Disabled => true,
+            Format::Mp4 | Format::Mkv | Format::Custom { .. } => true,
+            Format::Mov => codec != AudioCodec::Opus,
+            Format::Ts => matches!(codec, AudioCodec::Aac | AudioCodec::Mp3),
+            Format::M4a => codec == AudioCodec::Aac,
+            Format::Mp3 => codec == AudioCodec::Mp3,
+            Format::Webm | Format::Ogg | Format::Opus => codec == AudioCodec::Opus,
+        }
+    }
+
+    /// Check that the format can hold what profile encodes from a DR stream, which has H.264 video and AAC audio.
+    /// Custom formats are not checked.
+    pub fn verify_profile(&self, profile: &ConversionProfile) -> Result<()> {
+        self.verify_profile_for(profile, &SourceCodecs::default())
+    }
+
+    /// Check that the format can hold what profile encodes from an input with the codecs of source.
+    /// Streams copied from an input with unknown codecs are not checked.
+    pub fn verify_profile_for(
+        &self,
+        profile: &ConversionProfile,
+        source: &SourceCodecs,
+    ) -> Result<()> {
+        let video = profile.get_video_codec();
+        let video_out = match video {
+            VideoCodec::Copy => source.video,
+            x => Some(x),
+        };
+        if let Some(codec) = video_out.filter(|x| !self.supports_video(*x)) {
+            return Err(match video {
+                _ if self.is_audio_only() => {
+                    format!(
+                        "{} files cannot hold video, save only the audio instead.",
+                        self
+                    )
+                }
+                VideoCodec::Copy => format!(
+                    "{} files cannot hold the {:?} video of the stream without re-encoding it.",
+                    self, codec
+                ),
+                x => format!("{} files cannot hold {:?} video.", self, x),
+            }
+            .into());
+        }
+        let audio = profile.get_audio_codec();
+        let audio_out = match audio {
+            AudioCodec::Copy => source.audio,
+            x => Some(x),
+        };
+        if let Some(codec) = audio_out.filter(|x| !self.supports_audio(*x)) {
+            return Err(match audio {
+                AudioCodec::Copy => format!(
+                    "{} files cannot hold the {:?} audio of the stream without re-encoding it.",
+                    self, codec
+                ),
+                x => format!("{} files cannot hold {:?} audio.", self, x),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Check that subtitles can be embedded in the format.
+    pub fn verify_subtitles(&self) -> Result<()> {
+        match self.get_subtitle_codec() {
+            Some(_) => Ok(()),
+            None if matches!(self, Format::Custom { .. }) => {
+                Err("Subtitles cannot be embedded in custom formats.".into())
+            }
+            None => Err(format!("{} files cannot hold subtitles.", self).into()),
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.get_extension().trim_start_matches('.').to_uppercase()
+        )
     }
 }
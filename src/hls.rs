@@ -0,0 +1,461 @@
+use crate::error::{OkOrGeneric, Result};
+use crate::models::VariantInfo;
+use reqwest::Url;
+use std::collections::HashMap;
+
+/// A single stream variant in a master playlist.
+#[derive(Clone, Debug)]
+pub struct Variant {
+    pub uri: String,
+    pub bandwidth: u64,
+    pub codecs: Option<String>,
+    pub resolution: Option<String>,
+}
+
+/// An alternative rendition in a master playlist, such as an audio or subtitle track.
+#[derive(Clone, Debug)]
+pub struct Rendition {
+    /// AUDIO, VIDEO, SUBTITLES or CLOSED-CAPTIONS.
+    pub kind: String,
+    pub uri: Option<String>,
+    pub default: bool,
+}
+
+/// Which stream of a master playlist to download.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamSelection {
+    /// The variant with the highest bandwidth.
+    Best,
+    /// An audio only rendition or variant if there is one, otherwise the variant with the lowest bandwidth.
+    AudioOnly,
+}
+
+/// A range of bytes of a resource.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ByteRange {
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// A segment of a media playlist.
+#[derive(Clone, Debug)]
+pub struct Segment {
+    pub uri: String,
+    pub duration: f64,
+    /// The part of the resource at uri that is the segment, or None if it is all of it.
+    pub byte_range: Option<ByteRange>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MasterPlaylist {
+    pub variants: Vec<Variant>,
+    pub renditions: Vec<Rendition>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MediaPlaylist {
+    pub segments: Vec<Segment>,
+}
+
+impl MediaPlaylist {
+    /// Get the total duration of the segments in seconds.
+    pub fn duration(&self) -> f64 {
+        self.segments.iter().map(|x| x.duration).sum()
+    }
+}
+
+impl ByteRange {
+    /// Get the value of a Range header requesting the range.
+    pub fn header_value(&self) -> String {
+        format!("bytes={}-{}", self.offset, self.offset + self.length - 1)
+    }
+
+    fn end(&self) -> u64 {
+        self.offset + self.length
+    }
+}
+
+impl Variant {
+    /// Returns true if the codecs of the variant are known and contain no video codec.
+    pub fn is_audio_only(&self) -> bool {
+        const VIDEO_CODECS: &[&str] = &["avc", "hvc", "hev", "av01", "vp0", "mp4v"];
+        match &self.codecs {
+            Some(codecs) => !codecs
+                .split(',')
+                .any(|x| VIDEO_CODECS.iter().any(|v| x.trim().starts_with(v))),
+            None => false,
+        }
+    }
+
+    fn info(&self) -> VariantInfo {
+        VariantInfo {
+            uri: self.uri.clone(),
+            bandwidth: Some(self.bandwidth).filter(|x| *x != 0),
+            codecs: self.codecs.clone(),
+            resolution: self.resolution.clone(),
+            audio_rendition: false,
+        }
+    }
+}
+
+impl MasterPlaylist {
+    /// Get the variant with the highest bandwidth.
+    pub fn best_variant(&self) -> Option<&Variant> {
+        self.variants.iter().max_by_key(|x| x.bandwidth)
+    }
+
+    /// Get the audio only stream that is cheapest to download.
+    fn audio_only(&self) -> Option<VariantInfo> {
+        let rendition = self
+            .renditions
+            .iter()
+            .filter(|x| x.kind == "AUDIO" && x.uri.is_some())
+            .max_by_key(|x| x.default);
+        if let Some(uri) = rendition.and_then(|x| x.uri.clone()) {
+            return Some(VariantInfo {
+                uri,
+                audio_rendition: true,
+                ..Default::default()
+            });
+        }
+        let variant = self
+            .variants
+            .iter()
+            .filter(|x| x.is_audio_only())
+            .max_by_key(|x| x.bandwidth)
+            .or_else(|| self.variants.iter().min_by_key(|x| x.bandwidth));
+        variant.map(Variant::info)
+    }
+
+    /// Get the media playlist to download for selection.
+    pub fn select(&self, selection: StreamSelection) -> Option<VariantInfo> {
+        match selection {
+            StreamSelection::Best => self.best_variant().map(Variant::info),
+            StreamSelection::AudioOnly => self.audio_only(),
+        }
+    }
+}
+
+/// Returns true if the playlist is a master playlist.
+pub fn is_master(playlist: &str) -> bool {
+    playlist.contains("#EXT-X-STREAM-INF")
+}
+
+fn resolve_uri(base: &Url, uri: &str) -> Result<String> {
+    Ok(base.join(uri)?.to_string())
+}
+
+/// Parse a byte range of the form <length>[@<offset>], where a missing offset is taken from previous_end.
+fn parse_byte_range(value: &str, previous_end: Option<u64>) -> Result<ByteRange> {
+    let invalid = || format!("Media playlist contained an invalid byte range: {}", value);
+    let (length, offset) = match value.split_once('@') {
+        Some((length, offset)) => (length, Some(offset.trim().parse().map_err(|_| invalid())?)),
+        None => (value, None),
+    };
+    let length = length
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .filter(|x| *x > 0)
+        .ok_or_else(invalid)?;
+    let offset = offset.or(previous_end).ok_or_else(|| {
+        format!(
+            "Media playlist contained a byte range without an offset that does not follow another range of the same resource: {}",
+            value
+        )
+    })?;
+    offset.checked_add(length).ok_or_else(invalid)?;
+    Ok(ByteRange { offset, length })
+}
+
+fn parse_attributes(line: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = line;
+    while !rest.is_empty() {
+        let eq = match rest.find('=') {
+            Some(x) => x,
+            None => break,
+        };
+        let key = rest[..eq].trim().to_owned();
+        rest = &rest[eq + 1..];
+        let value;
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            value = quoted[..end].to_owned();
+            rest = quoted.get(end + 1..).unwrap_or("");
+        } else {
+            let end = rest.find(',').unwrap_or(rest.len());
+            value = rest[..end].to_owned();
+            rest = &rest[end..];
+        }
+        rest = rest.strip_prefix(',').unwrap_or(rest);
+        attributes.insert(key, value);
+    }
+    attributes
+}
+
+/// Parse a master playlist fetched from base_url.
+pub fn parse_master(base_url: &str, playlist: &str) -> Result<MasterPlaylist> {
+    let base = Url::parse(base_url)?;
+    let mut master = MasterPlaylist::default();
+    let mut lines = playlist.lines().map(str::trim);
+    while let Some(line) = lines.next() {
+        if let Some(media) = line.strip_prefix("#EXT-X-MEDIA:") {
+            let mut attributes = parse_attributes(media);
+            master.renditions.push(Rendition {
+                kind: attributes.remove("TYPE").unwrap_or_default(),
+                uri: match attributes.get("URI") {
+                    Some(uri) => Some(resolve_uri(&base, uri)?),
+                    None => None,
+                },
+                default: attributes.get("DEFAULT").map(String::as_str) == Some("YES"),
+            });
+            continue;
+        }
+        let attributes = match line.strip_prefix("#EXT-X-STREAM-INF:") {
+            Some(x) => parse_attributes(x),
+            None => continue,
+        };
+        let uri = lines
+            .find(|x| !x.is_empty() && !x.starts_with('#'))
+            .ok_or_generic("Stream info was not followed by an URI.")?;
+        master.variants.push(Variant {
+            uri: resolve_uri(&base, uri)?,
+            bandwidth: attributes
+                .get("BANDWIDTH")
+                .and_then(|x| x.parse().ok())
+                .unwrap_or(0),
+            codecs: attributes.get("CODECS").cloned(),
+            resolution: attributes.get("RESOLUTION").cloned(),
+        });
+    }
+    Ok(master)
+}
+
+/// Parse a media playlist fetched from base_url.
+/// Initialization segments of EXT-X-MAP are included as segments without a duration, in front of the segments they apply to.
+pub fn parse_media(base_url: &str, playlist: &str) -> Result<MediaPlaylist> {
+    let base = Url::parse(base_url)?;
+    let mut media = MediaPlaylist::default();
+    let mut duration = 0.0;
+    let mut byte_range = None;
+    let mut has_media = false;
+    for line in playlist.lines().map(str::trim) {
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            let end = info.find(',').unwrap_or(info.len());
+            duration = info[..end]
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|x| x.is_finite() && *x >= 0.0)
+                .ok_or_else(|| {
+                    format!(
+                        "Media playlist contained an invalid segment duration: {}",
+                        line
+                    )
+                })?;
+            continue;
+        }
+        if let Some(key) = line.strip_prefix("#EXT-X-KEY:") {
+            let attributes = parse_attributes(key);
+            if attributes.get("METHOD").map(String::as_str) != Some("NONE") {
+                return Err("Encrypted HLS streams are not supported.".into());
+            }
+            continue;
+        }
+        if let Some(map) = line.strip_prefix("#EXT-X-MAP:") {
+            let attributes = parse_attributes(map);
+            let uri = attributes
+                .get("URI")
+                .ok_or_generic("Media playlist contained an EXT-X-MAP without an URI.")?;
+            media.segments.push(Segment {
+                uri: resolve_uri(&base, uri)?,
+                duration: 0.0,
+                byte_range: match attributes.get("BYTERANGE") {
+                    Some(x) => Some(parse_byte_range(x, Some(0))?),
+                    None => None,
+                },
+            });
+            continue;
+        }
+        if let Some(range) = line.strip_prefix("#EXT-X-BYTERANGE:") {
+            byte_range = Some(range.to_owned());
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        let uri = resolve_uri(&base, line)?;
+        let byte_range = match byte_range.take() {
+            Some(range) => {
+                // Without an offset, a range starts where the previous range of the same resource ended.
+                let previous_end = media
+                    .segments
+                    .last()
+                    .filter(|x| x.uri == uri)
+                    .and_then(|x| x.byte_range)
+                    .map(|x| x.end());
+                Some(parse_byte_range(&range, previous_end)?)
+            }
+            None => None,
+        };
+        media.segments.push(Segment {
+            uri,
+            duration,
+            byte_range,
+        });
+        has_media = true;
+        duration = 0.0;
+    }
+    // Initialization segments alone are no media.
+    if !has_media {
+        return Err("Media playlist contained no segments.".into());
+    }
+    Ok(media)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "https://example.com/stream/master.m3u8?token=abc";
+
+    const MASTER: &str = "#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"Dansk\",DEFAULT=YES,URI=\"audio/index.m3u8\"
+#EXT-X-STREAM-INF:BANDWIDTH=800000,CODECS=\"avc1.4d401f,mp4a.40.2\",RESOLUTION=640x360,AUDIO=\"aac\"
+low/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=5000000,CODECS=\"avc1.640028,mp4a.40.2\",RESOLUTION=1920x1080,AUDIO=\"aac\"
+
+https://cdn.example.com/high/index.m3u8
+";
+
+    #[test]
+    fn parse_master_reads_variants_and_renditions() {
+        let master = parse_master(BASE, MASTER).unwrap();
+        assert_eq!(master.variants.len(), 2);
+        let low = &master.variants[0];
+        assert_eq!(low.uri, "https://example.com/stream/low/index.m3u8");
+        assert_eq!(low.bandwidth, 800000);
+        assert_eq!(low.codecs.as_deref(), Some("avc1.4d401f,mp4a.40.2"));
+        assert_eq!(low.resolution.as_deref(), Some("640x360"));
+        assert_eq!(
+            master.variants[1].uri,
+            "https://cdn.example.com/high/index.m3u8"
+        );
+        assert_eq!(master.renditions.len(), 1);
+        assert_eq!(master.renditions[0].kind, "AUDIO");
+        assert!(master.renditions[0].default);
+        assert_eq!(
+            master.renditions[0].uri.as_deref(),
+            Some("https://example.com/stream/audio/index.m3u8")
+        );
+    }
+
+    #[test]
+    fn parse_master_selects_streams() {
+        let master = parse_master(BASE, MASTER).unwrap();
+        let best = master.select(StreamSelection::Best).unwrap();
+        assert_eq!(best.bandwidth, Some(5000000));
+        let audio = master.select(StreamSelection::AudioOnly).unwrap();
+        assert!(audio.audio_rendition);
+        assert_eq!(audio.uri, "https://example.com/stream/audio/index.m3u8");
+    }
+
+    #[test]
+    fn parse_master_requires_uri_after_stream_info() {
+        let playlist = "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=1\n";
+        assert!(parse_master(BASE, playlist).is_err());
+    }
+
+    #[test]
+    fn parse_media_reads_segments() {
+        let playlist = "#EXTM3U
+#EXT-X-TARGETDURATION:6
+#EXTINF:6.0,
+seg0.ts
+#EXTINF:4.5,title
+seg1.ts
+#EXT-X-ENDLIST
+";
+        let media = parse_media(BASE, playlist).unwrap();
+        assert_eq!(media.segments.len(), 2);
+        assert_eq!(media.segments[0].uri, "https://example.com/stream/seg0.ts");
+        assert_eq!(media.segments[1].duration, 4.5);
+        assert_eq!(media.duration(), 10.5);
+    }
+
+    #[test]
+    fn parse_media_rejects_invalid_durations() {
+        for duration in ["-1", "NaN", "inf", "abc"] {
+            let playlist = format!("#EXTM3U\n#EXTINF:{},\nseg0.ts\n", duration);
+            assert!(parse_media(BASE, &playlist).is_err(), "{}", duration);
+        }
+    }
+
+    #[test]
+    fn parse_media_rejects_encrypted_and_empty_playlists() {
+        let encrypted = "#EXTM3U\n#EXT-X-KEY:METHOD=AES-128,URI=\"key\"\n#EXTINF:6,\nseg0.ts\n";
+        assert!(parse_media(BASE, encrypted).is_err());
+        let unencrypted = "#EXTM3U\n#EXT-X-KEY:METHOD=NONE\n#EXTINF:6,\nseg0.ts\n";
+        assert!(parse_media(BASE, unencrypted).is_ok());
+        assert!(parse_media(BASE, "#EXTM3U\n#EXT-X-ENDLIST\n").is_err());
+    }
+
+    #[test]
+    fn parse_media_reads_init_segments_and_byte_ranges() {
+        let playlist = "#EXTM3U
+#EXT-X-MAP:URI=\"main.mp4\",BYTERANGE=\"720@0\"
+#EXTINF:6.0,
+#EXT-X-BYTERANGE:1000@720
+main.mp4
+#EXTINF:6.0,
+#EXT-X-BYTERANGE:500
+main.mp4
+#EXT-X-MAP:URI=\"init2.mp4\"
+#EXTINF:2.0,
+seg2.m4s
+";
+        let media = parse_media(BASE, playlist).unwrap();
+        let ranges = media
+            .segments
+            .iter()
+            .map(|x| x.byte_range.map(|x| (x.offset, x.length)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ranges,
+            [
+                Some((0, 720)),
+                Some((720, 1000)),
+                Some((1720, 500)),
+                None,
+                None
+            ]
+        );
+        assert_eq!(media.segments[0].duration, 0.0);
+        assert_eq!(
+            media.segments[3].uri,
+            "https://example.com/stream/init2.mp4"
+        );
+        assert_eq!(media.duration(), 14.0);
+        assert_eq!(
+            media.segments[1].byte_range.unwrap().header_value(),
+            "bytes=720-1719"
+        );
+    }
+
+    #[test]
+    fn parse_media_rejects_invalid_byte_ranges() {
+        for range in ["0@0", "abc", "10@x", "18446744073709551615@1"] {
+            let playlist = format!("#EXTM3U\n#EXTINF:6,\n#EXT-X-BYTERANGE:{}\nseg0.ts\n", range);
+            assert!(parse_media(BASE, &playlist).is_err(), "{}", range);
+        }
+        // Without an offset, a range must follow a range of the same resource.
+        let playlist = "#EXTM3U\n#EXTINF:6,\n#EXT-X-BYTERANGE:10@0\nseg0.ts\n#EXTINF:6,\n#EXT-X-BYTERANGE:10\nseg1.ts\n";
+        assert!(parse_media(BASE, playlist).is_err());
+        let init_only = "#EXTM3U\n#EXT-X-MAP:URI=\"init.mp4\"\n#EXT-X-ENDLIST\n";
+        assert!(parse_media(BASE, init_only).is_err());
+    }
+}
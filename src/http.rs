@@ -0,0 +1,54 @@
+use crate::util::redact_url;
+use reqwest::{Client, RequestBuilder, Response};
+use std::time::Instant;
+use tracing::{field, Instrument};
+
+/// Send a request within a span recording its method, url, status and latency.
+pub(crate) async fn send(client: &Client, request: RequestBuilder) -> reqwest::Result<Response> {
+    send_inner(client, request, None).await
+}
+
+/// Send a request like send, recording retry as the number of times it has been retried.
+pub(crate) async fn send_retry(
+    client: &Client,
+    request: RequestBuilder,
+    retry: u32,
+) -> reqwest::Result<Response> {
+    send_inner(client, request, Some(retry)).await
+}
+
+async fn send_inner(
+    client: &Client,
+    request: RequestBuilder,
+    retry: Option<u32>,
+) -> reqwest::Result<Response> {
+    // The url is left out of errors, as its query may hold tokens.
+    let request = request.build().map_err(|e| e.without_url())?;
+    let span = tracing::debug_span!(
+        "http_request",
+        method = %request.method(),
+        url = %redact_url(request.url().as_str()),
+        retry = field::Empty,
+        status = field::Empty,
+        latency_ms = field::Empty,
+    );
+    if let Some(retry) = retry {
+        span.record("retry", retry);
+    }
+    async move {
+        let start = Instant::now();
+        let result = client.execute(request).await.map_err(|e| e.without_url());
+        let span = tracing::Span::current();
+        span.record("latency_ms", start.elapsed().as_millis() as u64);
+        match &result {
+            Ok(response) => {
+                span.record("status", response.status().as_u16());
+                tracing::debug!("request finished");
+            }
+            Err(e) => tracing::warn!(error = %e, "request failed"),
+        }
+        result
+    }
+    .instrument(span)
+    .await
+}
@@ -0,0 +1,176 @@
+use crate::error::Result;
+use crate::format::Format;
+use crate::models::episode::{EpisodeInfo, EpisodeStream, NETWORK};
+use crate::models::VariantInfo;
+use crate::util::format_utc_timestamp;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::io::AsyncReadExt;
+
+/// What the info.json of a saved episode describes.
+pub(crate) struct SavedEpisode<'a> {
+    pub episode: &'a EpisodeInfo,
+    pub stream: &'a EpisodeStream,
+    pub variant: &'a VariantInfo,
+    pub subtitles_embedded: bool,
+    pub format: &'a Format,
+    /// The path of the saved media file.
+    pub path: &'a Path,
+}
+
+/// Get the path of the info.json of the media file at path.
+pub(crate) fn info_path(path: &Path) -> PathBuf {
+    path.with_extension("info.json")
+}
+
+/// Get the id of the episode the info.json next to the media file at path describes, if there is one.
+pub(crate) fn episode_id(path: &Path) -> Option<String> {
+    let text = std::fs::read_to_string(info_path(path)).ok()?;
+    let json: Value = serde_json::from_str(&text).ok()?;
+    json["id"].as_str().map(str::to_owned)
+}
+
+/// Get the SHA-256 checksum of the file at path, in hex.
+async fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        let read = file.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|x| format!("{:02x}", x))
+        .collect())
+}
+
+fn host(url: &str) -> Option<String> {
+    reqwest::Url::parse(url).ok()?.host_str().map(str::to_owned)
+}
+
+/// Get the info.json of a saved episode. The output is read to compute its checksum.
+pub(crate) async fn build(saved: &SavedEpisode<'_>) -> Result<String> {
+    let episode = saved.episode;
+    let meta = &episode.metadata;
+    let variant = saved.variant;
+    let subtitles = saved
+        .stream
+        .subtitles
+        .iter()
+        .map(|x| {
+            json!({
+                "language": x.language,
+                "name": x.name,
+                "default": x.default,
+                "forced": x.forced,
+            })
+        })
+        .collect::<Vec<_>>();
+    let file = tokio::fs::metadata(saved.path).await?;
+    let json: Value = json!({
+        "id": episode.id,
+        "name": episode.name,
+        "title": episode.title(),
+        "show": meta.show,
+        "show_id": meta.show_id,
+        "season": meta.season,
+        "episode": meta.episode,
+        "description": meta.description,
+        "air_date": meta.air_date,
+        "genres": meta.genres,
+        "network": NETWORK,
+        "variant": {
+            "bandwidth": variant.bandwidth,
+            "codecs": variant.codecs,
+            "resolution": variant.resolution,
+            "audio_rendition": variant.audio_rendition,
+        },
+        "stream_host": host(&saved.stream.url),
+        "subtitles": subtitles,
+        "subtitles_embedded": saved.subtitles_embedded,
+        "file": {
+            "name": saved.path.file_name().map(|x| x.to_string_lossy()),
+            "format": saved.format.get_extension().trim_start_matches('.'),
+            "size": file.len(),
+            "sha256": sha256_file(saved.path).await?,
+        },
+        "downloaded_at": format_utc_timestamp(SystemTime::now()),
+        "downloader": {
+            "name": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+    });
+    Ok(serde_json::to_string_pretty(&json)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::episode::EpisodeMetadata;
+    use crate::models::SubtitleTrack;
+
+    #[tokio::test]
+    async fn build_describes_the_stream_subtitles_and_file() {
+        let path = std::env::temp_dir().join(format!(
+            "dr-downloader-info-json-{}.mp4",
+            std::process::id()
+        ));
+        std::fs::write(&path, b"media").unwrap();
+        let episode = EpisodeInfo {
+            name: "tv-avisen".to_owned(),
+            id: "67890".to_owned(),
+            metadata: EpisodeMetadata::default(),
+        };
+        let stream = EpisodeStream {
+            url: "https://drod.example.com/high/index.m3u8?hdnts=secret".to_owned(),
+            subtitles: vec![SubtitleTrack {
+                url: "https://drod.example.com/subs/dan.vtt".to_owned(),
+                language: Some("dan".to_owned()),
+                name: Some("Dansk".to_owned()),
+                default: true,
+                forced: false,
+            }],
+        };
+        let variant = VariantInfo {
+            uri: "https://drod.example.com/high/index.m3u8".to_owned(),
+            bandwidth: Some(5_000_000),
+            codecs: None,
+            resolution: Some("1920x1080".to_owned()),
+            audio_rendition: false,
+        };
+        let saved = SavedEpisode {
+            episode: &episode,
+            stream: &stream,
+            variant: &variant,
+            subtitles_embedded: true,
+            format: &Format::Mp4,
+            path: &path,
+        };
+        let text = build(&saved).await;
+        std::fs::remove_file(&path).ok();
+        let json: Value = serde_json::from_str(&text.unwrap()).unwrap();
+
+        // Only the host of the stream is kept, not its token.
+        assert_eq!(json["stream_host"], "drod.example.com");
+        assert_eq!(
+            json["subtitles"],
+            json!([{ "language": "dan", "name": "Dansk", "default": true, "forced": false }])
+        );
+        assert_eq!(json["subtitles_embedded"], true);
+        assert_eq!(json["file"]["format"], "mp4");
+        assert_eq!(json["file"]["size"], 5);
+        assert_eq!(
+            json["file"]["sha256"],
+            "721c9525ade2ea8903d343ef25cf68b9bf4ab0aad56bb7b01fbe48d09bc7fcf4"
+        );
+        assert_eq!(json["id"], "67890");
+        assert_eq!(json["title"], "tv-avisen");
+    }
+}
@@ -0,0 +1,167 @@
+use crate::error::{Cancelled, Result};
+use std::sync::Arc;
+use tokio::sync::watch;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Paused,
+    Cancelled,
+}
+
+/// A handle for pausing, resuming and cancelling a job.
+/// Clones share the same state, so one can be kept while another is passed to the job.
+#[derive(Clone)]
+pub struct JobHandle {
+    state: Arc<watch::Sender<JobState>>,
+}
+
+impl Default for JobHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JobHandle {
+    pub fn new() -> Self {
+        let (state, _) = watch::channel(JobState::Running);
+        JobHandle {
+            state: Arc::new(state),
+        }
+    }
+
+    pub fn state(&self) -> JobState {
+        *self.state.borrow()
+    }
+
+    /// Pause the job. Downloads stop before their next segment.
+    pub fn pause(&self) {
+        self.set_state(JobState::Paused);
+    }
+
+    /// Resume a paused job.
+    pub fn resume(&self) {
+        self.set_state(JobState::Running);
+    }
+
+    /// Cancel the job. This cannot be undone.
+    pub fn cancel(&self) {
+        self.state.send_replace(JobState::Cancelled);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state() == JobState::Cancelled
+    }
+
+    fn set_state(&self, state: JobState) {
+        self.state.send_if_modified(|x| {
+            if *x == JobState::Cancelled || *x == state {
+                return false;
+            }
+            *x = state;
+            true
+        });
+    }
+
+    /// Wait while the job is paused. Returns an error if the job was cancelled.
+    pub(crate) async fn checkpoint(&self) -> Result<()> {
+        let mut rx = self.state.subscribe();
+        let state = *rx
+            .wait_for(|x| *x != JobState::Paused)
+            .await
+            .map_err(|_| Cancelled)?;
+        match state {
+            JobState::Cancelled => Err(Cancelled.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Completes when the job is cancelled.
+    pub(crate) async fn cancelled(&self) {
+        let mut rx = self.state.subscribe();
+        rx.wait_for(|x| *x == JobState::Cancelled).await.ok();
+    }
+
+    /// Apply the state of this job to other until this job is cancelled, which cancels other as well.
+    pub(crate) async fn forward_to(&self, other: &JobHandle) {
+        let mut rx = self.state.subscribe();
+        loop {
+            let state = *rx.borrow_and_update();
+            match state {
+                JobState::Running => other.resume(),
+                JobState::Paused => other.pause(),
+                JobState::Cancelled => {
+                    other.cancel();
+                    return;
+                }
+            }
+            if rx.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::is_cancelled;
+    use std::time::Duration;
+
+    /// Wait until condition holds, failing the test if it takes too long.
+    async fn wait_until(condition: impl Fn() -> bool) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !condition() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("condition was never met");
+    }
+
+    #[tokio::test]
+    async fn checkpoint_waits_while_paused() {
+        let handle = JobHandle::new();
+        handle.pause();
+        let job = handle.clone();
+        let task = tokio::spawn(async move { job.checkpoint().await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!task.is_finished());
+        handle.resume();
+        assert!(task.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn cancel_stops_a_paused_job_for_good() {
+        let handle = JobHandle::new();
+        handle.pause();
+        let job = handle.clone();
+        let task = tokio::spawn(async move { job.checkpoint().await });
+        handle.cancel();
+        let err = task.await.unwrap().unwrap_err();
+        assert!(is_cancelled(err.as_ref()));
+        handle.resume();
+        assert_eq!(handle.state(), JobState::Cancelled);
+        assert!(handle.checkpoint().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn forward_to_applies_state_until_cancelled() {
+        let outer = JobHandle::new();
+        let inner = JobHandle::new();
+        let forward = {
+            let (outer, inner) = (outer.clone(), inner.clone());
+            tokio::spawn(async move { outer.forward_to(&inner).await })
+        };
+        outer.pause();
+        wait_until(|| inner.state() == JobState::Paused).await;
+        outer.resume();
+        wait_until(|| inner.state() == JobState::Running).await;
+        outer.cancel();
+        tokio::time::timeout(Duration::from_secs(5), forward)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(inner.is_cancelled());
+    }
+}
@@ -1,14 +1,33 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub mod batch;
+pub mod blocking;
 pub mod cacher;
 pub mod converter;
 pub mod downloader;
 pub mod error;
+pub mod event;
+pub mod ffmpeg;
+pub mod filename;
 pub mod format;
+pub mod job;
+pub mod models;
+pub mod profile;
+pub mod progress;
+pub mod queue;
+pub mod report;
 pub mod requester;
 pub mod saver;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod template;
 
-mod event;
-mod models;
+mod hls;
+mod http;
+mod info_json;
+mod mp4;
+mod nfo;
+mod remux;
+mod resume;
 mod util;
@@ -0,0 +1,78 @@
+use super::episode::EpisodeInfo;
+use crate::progress::{ConvertProgress, Progress};
+use std::path::PathBuf;
+
+/// An event in the download of an episode.
+#[derive(Clone, Debug)]
+pub enum DownloadEvent {
+    /// The stream of the episode was found.
+    Resolved {
+        episode: EpisodeInfo,
+        stream_url: String,
+    },
+    Started {
+        episode: EpisodeInfo,
+    },
+    Progress {
+        episode: EpisodeInfo,
+        progress: Progress,
+    },
+    ConvertStarted {
+        episode: EpisodeInfo,
+        path: PathBuf,
+    },
+    ConvertProgress {
+        episode: EpisodeInfo,
+        progress: ConvertProgress,
+    },
+    /// The episode was downloaded. Path is None when it was only downloaded to memory.
+    Finished {
+        episode: EpisodeInfo,
+        path: Option<PathBuf>,
+        bytes: u64,
+    },
+    Skipped {
+        episode: EpisodeInfo,
+        reason: String,
+    },
+    Cancelled {
+        episode: EpisodeInfo,
+    },
+    Failed {
+        episode: EpisodeInfo,
+        /// The message of the error, which is returned to the caller as is.
+        error: String,
+    },
+}
+
+impl DownloadEvent {
+    /// Get the episode the event is about.
+    pub fn episode(&self) -> &EpisodeInfo {
+        match self {
+            DownloadEvent::Resolved { episode, .. }
+            | DownloadEvent::Started { episode }
+            | DownloadEvent::Progress { episode, .. }
+            | DownloadEvent::ConvertStarted { episode, .. }
+            | DownloadEvent::ConvertProgress { episode, .. }
+            | DownloadEvent::Finished { episode, .. }
+            | DownloadEvent::Skipped { episode, .. }
+            | DownloadEvent::Cancelled { episode }
+            | DownloadEvent::Failed { episode, .. } => episode,
+        }
+    }
+
+    /// Get the name of the kind of event, such as "progress".
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DownloadEvent::Resolved { .. } => "resolved",
+            DownloadEvent::Started { .. } => "started",
+            DownloadEvent::Progress { .. } => "progress",
+            DownloadEvent::ConvertStarted { .. } => "convert_started",
+            DownloadEvent::ConvertProgress { .. } => "convert_progress",
+            DownloadEvent::Finished { .. } => "finished",
+            DownloadEvent::Skipped { .. } => "skipped",
+            DownloadEvent::Cancelled { .. } => "cancelled",
+            DownloadEvent::Failed { .. } => "failed",
+        }
+    }
+}
@@ -1,10 +1,88 @@
-#[derive(Clone)]
+use super::SubtitleTrack;
+
+/// The network written to the tags of saved episodes.
+pub const NETWORK: &str = "DR";
+
+/// Descriptive information about an episode, as far as DR provides it.
+#[derive(Clone, Debug, Default)]
+pub struct EpisodeMetadata {
+    pub title: Option<String>,
+    pub show: Option<String>,
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+    pub description: Option<String>,
+    /// The date the episode first aired, in the form YYYY-MM-DD.
+    pub air_date: Option<String>,
+    pub genres: Vec<String>,
+    pub poster_url: Option<String>,
+    pub show_id: Option<String>,
+    pub show_description: Option<String>,
+    pub show_poster_url: Option<String>,
+    /// A wide background image of the show.
+    pub fanart_url: Option<String>,
+}
+
+#[derive(Clone, Debug)]
 pub struct EpisodeInfo {
     pub name: String,
     pub id: String,
+    pub metadata: EpisodeMetadata,
+}
+
+impl EpisodeInfo {
+    /// Get the title of the episode, falling back to the name in its url.
+    pub fn title(&self) -> &str {
+        self.metadata.title.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Get the metadata as FFMPEG tags, leaving out what is unknown.
+    /// Both the video and music tag names are set, so players of either kind can show them.
+    /// They are written by FFMPEG when converting and natively when remuxing to MP4 without a Converter.
+    pub fn tags(&self) -> Vec<(String, String)> {
+        let meta = &self.metadata;
+        let mut tags = vec![("title", self.title().to_owned())];
+        if let Some(show) = &meta.show {
+            tags.extend([("show", show.clone()), ("album", show.clone())]);
+        }
+        if let Some(season) = meta.season {
+            tags.push(("season_number", season.to_string()));
+        }
+        if let Some(episode) = meta.episode {
+            tags.extend([
+                ("episode_sort", episode.to_string()),
+                ("track", episode.to_string()),
+            ]);
+        }
+        if let (Some(season), Some(episode)) = (meta.season, meta.episode) {
+            tags.push(("episode_id", format!("S{:02}E{:02}", season, episode)));
+        }
+        if let Some(description) = &meta.description {
+            tags.extend([
+                ("description", description.clone()),
+                ("synopsis", description.clone()),
+                ("comment", description.clone()),
+            ]);
+        }
+        if let Some(air_date) = &meta.air_date {
+            tags.push(("date", air_date.clone()));
+        }
+        if !meta.genres.is_empty() {
+            tags.push(("genre", meta.genres.join(", ")));
+        }
+        tags.push(("network", NETWORK.to_owned()));
+        tags.into_iter().map(|(k, v)| (k.to_owned(), v)).collect()
+    }
+}
+
+/// Where the media of an episode is streamed from.
+#[derive(Clone, Debug)]
+pub struct EpisodeStream {
+    /// The url of the HLS stream.
+    pub url: String,
+    pub subtitles: Vec<SubtitleTrack>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct EpisodeData {
     pub info: EpisodeInfo,
     pub data: Vec<u8>,
@@ -1,4 +1,10 @@
+mod download_event;
 pub mod episode;
+mod subtitle;
 mod url_type;
+mod variant_info;
 
+pub use download_event::DownloadEvent;
+pub use subtitle::SubtitleTrack;
 pub use url_type::URLType;
+pub use variant_info::VariantInfo;
@@ -0,0 +1,12 @@
+/// A subtitle track of an episode.
+#[derive(Clone, Debug)]
+pub struct SubtitleTrack {
+    /// Where the subtitles are read from, a url or a path.
+    pub url: String,
+    /// ISO 639-2 language code, such as "dan".
+    pub language: Option<String>,
+    pub name: Option<String>,
+    pub default: bool,
+    /// Whether the track only covers speech in another language than the main one.
+    pub forced: bool,
+}
@@ -0,0 +1,13 @@
+/// The HLS stream that was downloaded for an episode.
+#[derive(Clone, Debug, Default)]
+pub struct VariantInfo {
+    /// The url of the media playlist.
+    pub uri: String,
+    /// Peak bitrate in bits per second, if the stream had several variants.
+    pub bandwidth: Option<u64>,
+    pub codecs: Option<String>,
+    /// Such as "1920x1080".
+    pub resolution: Option<String>,
+    /// Whether an audio rendition was downloaded rather than a variant.
+    pub audio_rendition: bool,
+}
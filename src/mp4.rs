@@ -0,0 +1,470 @@
+use crate::error::Result;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// How the value of a tag is stored in an ilst item.
+#[derive(Clone, Copy)]
+enum TagKind {
+    Text,
+    Integer,
+    /// A track number, stored as its number and the number of tracks.
+    Track,
+}
+
+/// The ilst items of FFMPEG tag names, as the FFMPEG MP4 muxer writes them.
+const TAG_ITEMS: &[(&str, &[u8; 4], TagKind)] = &[
+    ("title", b"\xa9nam", TagKind::Text),
+    ("show", b"tvsh", TagKind::Text),
+    ("album", b"\xa9alb", TagKind::Text),
+    ("season_number", b"tvsn", TagKind::Integer),
+    ("episode_sort", b"tves", TagKind::Integer),
+    ("track", b"trkn", TagKind::Track),
+    ("episode_id", b"tven", TagKind::Text),
+    ("description", b"desc", TagKind::Text),
+    ("synopsis", b"ldes", TagKind::Text),
+    ("comment", b"\xa9cmt", TagKind::Text),
+    ("date", b"\xa9day", TagKind::Text),
+    ("genre", b"\xa9gen", TagKind::Text),
+    ("network", b"tvnt", TagKind::Text),
+];
+
+/// Boxes that only hold other boxes, on the way from the movie box to the chunk offsets.
+const OFFSET_CONTAINERS: &[&[u8; 4]] = &[b"trak", b"mdia", b"minf", b"stbl"];
+
+/// Get a box of kind holding body.
+pub(crate) fn make_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(body.len() + 16);
+    match u32::try_from(body.len() + 8) {
+        Ok(size) => {
+            out.extend(size.to_be_bytes());
+            out.extend(kind);
+        }
+        Err(_) => {
+            out.extend(1u32.to_be_bytes());
+            out.extend(kind);
+            out.extend((body.len() as u64 + 16).to_be_bytes());
+        }
+    }
+    out.extend(body);
+    out
+}
+
+/// Get a box of kind with a version and flags in front of body.
+pub(crate) fn make_full_box(kind: &[u8; 4], version: u8, flags: u32, body: &[u8]) -> Vec<u8> {
+    let mut full = Vec::with_capacity(body.len() + 4);
+    full.extend((u32::from(version) << 24 | flags & 0xFF_FFFF).to_be_bytes());
+    full.extend(body);
+    make_box(kind, &full)
+}
+
+fn tag_item(kind: TagKind, value: &str) -> Option<Vec<u8>> {
+    // The type of the data, as 1 is UTF-8, 21 is a signed big endian integer and 0 is binary.
+    let (data_type, payload) = match kind {
+        TagKind::Text => (1u32, value.as_bytes().to_vec()),
+        TagKind::Integer => (21, value.trim().parse::<i32>().ok()?.to_be_bytes().to_vec()),
+        TagKind::Track => {
+            let track = value.trim().parse::<u16>().ok()?;
+            let mut payload = vec![0, 0];
+            payload.extend(track.to_be_bytes());
+            payload.extend([0, 0, 0, 0]);
+            (0, payload)
+        }
+    };
+    let mut data = data_type.to_be_bytes().to_vec();
+    data.extend(0u32.to_be_bytes()); // The locale, where 0 is the default.
+    data.extend(payload);
+    Some(make_box(b"data", &data))
+}
+
+/// Get a user data box holding tags as iTunes style metadata, which players such as Plex, Jellyfin and iTunes read.
+/// Tags are FFMPEG tag names and values, and tags with no MP4 equivalent are left out.
+pub(crate) fn user_data_box(tags: &[(String, String)]) -> Vec<u8> {
+    let mut items = Vec::new();
+    for (name, value) in tags {
+        let item = TAG_ITEMS
+            .iter()
+            .find(|x| x.0 == name)
+            .and_then(|(_, kind, tag_kind)| Some((kind, tag_item(*tag_kind, value)?)));
+        match item {
+            Some((kind, data)) => items.extend(make_box(kind, &data)),
+            None => tracing::debug!(tag = %name, "leaving out tag without an MP4 equivalent"),
+        }
+    }
+    let mut handler = vec![0; 4];
+    handler.extend(b"mdirappl");
+    handler.extend([0; 9]);
+    let mut meta = make_full_box(b"hdlr", 0, 0, &handler);
+    meta.extend(make_box(b"ilst", &items));
+    make_box(b"udta", &make_full_box(b"meta", 0, 0, &meta))
+}
+
+/// Returns true if data starts like an MP4 file.
+pub(crate) fn is_mp4(data: &[u8]) -> bool {
+    matches!(
+        data.get(4..8),
+        Some(b"ftyp" | b"styp" | b"moov" | b"moof" | b"sidx")
+    )
+}
+
+/// A box read from a buffer.
+struct BoxRef<'a> {
+    kind: [u8; 4],
+    header_len: usize,
+    /// The whole box, header included.
+    data: &'a [u8],
+}
+
+impl<'a> BoxRef<'a> {
+    fn body(&self) -> &'a [u8] {
+        &self.data[self.header_len..]
+    }
+}
+
+/// Split data into the boxes it holds.
+fn boxes(mut data: &[u8]) -> Result<Vec<BoxRef<'_>>> {
+    let mut boxes = Vec::new();
+    while !data.is_empty() {
+        let invalid = || "MP4 file contained an invalid box.";
+        let header = data.get(..8).ok_or_else(invalid)?;
+        let kind = [header[4], header[5], header[6], header[7]];
+        let (size, header_len) =
+            match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+                0 => (data.len() as u64, 8),
+                1 => {
+                    let large = data.get(8..16).ok_or_else(invalid)?;
+                    (u64::from_be_bytes(large.try_into()?), 16)
+                }
+                x => (u64::from(x), 8),
+            };
+        let size = usize::try_from(size)
+            .ok()
+            .filter(|x| *x >= header_len && *x <= data.len())
+            .ok_or_else(invalid)?;
+        boxes.push(BoxRef {
+            kind,
+            header_len,
+            data: &data[..size],
+        });
+        data = &data[size..];
+    }
+    Ok(boxes)
+}
+
+/// Add shift to the chunk offsets inside the boxes of data, which is the body of a movie box or one of its containers.
+fn shift_chunk_offsets(data: &[u8], shift: i64) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len());
+    for child in boxes(data)? {
+        let body = child.body();
+        let entry_len = match &child.kind {
+            b"stco" => 4,
+            b"co64" => 8,
+            kind if OFFSET_CONTAINERS.contains(&kind) => {
+                out.extend(make_box(kind, &shift_chunk_offsets(body, shift)?));
+                continue;
+            }
+            _ => {
+                out.extend(child.data);
+                continue;
+            }
+        };
+        let mut body = body.to_vec();
+        for entry in body
+            .get_mut(8..)
+            .unwrap_or_default()
+            .chunks_exact_mut(entry_len)
+        {
+            if entry_len == 4 {
+                let offset = u32::from_be_bytes(entry.try_into()?);
+                let offset = u32::try_from(i64::from(offset) + shift)
+                    .map_err(|_| "Chunk offset did not fit after writing tags.")?;
+                entry.copy_from_slice(&offset.to_be_bytes());
+            } else {
+                let offset = u64::from_be_bytes(entry.try_into()?);
+                let offset = offset
+                    .checked_add_signed(shift)
+                    .ok_or("Chunk offset did not fit after writing tags.")?;
+                entry.copy_from_slice(&offset.to_be_bytes());
+            }
+        }
+        out.extend(make_box(&child.kind, &body));
+    }
+    Ok(out)
+}
+
+/// Replace the metadata in the user data of the body of a movie box with tags.
+fn tag_movie(movie: &[u8], tags: &[(String, String)]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(movie.len());
+    let mut user_data = Vec::new();
+    for child in boxes(movie)? {
+        if &child.kind != b"udta" {
+            out.extend(child.data);
+            continue;
+        }
+        for item in boxes(child.body())? {
+            if &item.kind != b"meta" {
+                user_data.extend(item.data);
+            }
+        }
+    }
+    // The other user data is kept, with the new metadata in place of the old.
+    let tagged = user_data_box(tags);
+    user_data.extend(&tagged[8..]);
+    out.extend(make_box(b"udta", &user_data));
+    Ok(out)
+}
+
+/// Add shift to the base data offset of the track fragments of the body of a movie fragment box, where it is set.
+fn shift_fragment_offsets(fragment: &[u8], shift: i64) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(fragment.len());
+    for child in boxes(fragment)? {
+        if &child.kind != b"traf" {
+            out.extend(child.data);
+            continue;
+        }
+        let mut track = Vec::new();
+        for item in boxes(child.body())? {
+            let body = item.body();
+            // The flag 1 is set when a base data offset follows the track id.
+            let has_base = body.get(3).is_some_and(|x| x & 1 != 0);
+            if &item.kind != b"tfhd" || !has_base {
+                track.extend(item.data);
+                continue;
+            }
+            let mut body = body.to_vec();
+            let base = body
+                .get_mut(8..16)
+                .ok_or("MP4 file contained an invalid track fragment header.")?;
+            let offset = u64::from_be_bytes((&*base).try_into()?)
+                .checked_add_signed(shift)
+                .ok_or("Fragment offset did not fit after writing tags.")?;
+            base.copy_from_slice(&offset.to_be_bytes());
+            track.extend(make_box(b"tfhd", &body));
+        }
+        out.extend(make_box(b"traf", &track));
+    }
+    Ok(out)
+}
+
+/// The header of a top level box of a file.
+struct Header {
+    kind: [u8; 4],
+    bytes: Vec<u8>,
+    /// The length of the body, or None for a box that extends to the end of the file.
+    body_len: Option<u64>,
+}
+
+/// Read the header of the next top level box, if there is one.
+fn read_header(input: &mut impl Read) -> io::Result<Option<Header>> {
+    let mut header = vec![0; 8];
+    match input.read_exact(&mut header) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        x => x?,
+    }
+    let kind = [header[4], header[5], header[6], header[7]];
+    let size = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+        0 => {
+            return Ok(Some(Header {
+                kind,
+                bytes: header,
+                body_len: None,
+            }))
+        }
+        1 => {
+            let mut large = [0; 8];
+            input.read_exact(&mut large)?;
+            header.extend(large);
+            u64::from_be_bytes(large)
+        }
+        x => u64::from(x),
+    };
+    let body_len = size.checked_sub(header.len() as u64).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "MP4 file contained an invalid box.",
+        )
+    })?;
+    Ok(Some(Header {
+        kind,
+        bytes: header,
+        body_len: Some(body_len),
+    }))
+}
+
+fn read_body(input: &mut impl Read, len: u64) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    input.take(len).read_to_end(&mut body)?;
+    if body.len() as u64 != len {
+        return Err("MP4 file ended inside a box.".into());
+    }
+    Ok(body)
+}
+
+/// Copy the MP4 file at input to output with tags written into its movie box, replacing the tags it had.
+/// The offsets that point past the movie box are moved along with the data, and the random access index
+/// of a fragmented file is left out, as it is optional and its offsets would no longer be right.
+pub(crate) fn write_tags(input: &Path, output: &Path, tags: &[(String, String)]) -> Result<()> {
+    let mut input = BufReader::new(File::open(input)?);
+    let mut output = BufWriter::new(File::create(output)?);
+    // How far the data read so far has moved.
+    let mut shift = 0i64;
+    let mut seen_movie = false;
+    let mut seen_data = false;
+    while let Some(Header {
+        kind,
+        bytes: header,
+        body_len,
+    }) = read_header(&mut input)?
+    {
+        let body_len = match body_len {
+            Some(x) => x,
+            None => {
+                output.write_all(&header)?;
+                io::copy(&mut input, &mut output)?;
+                break;
+            }
+        };
+        match &kind {
+            b"moov" => {
+                let body = read_body(&mut input, body_len)?;
+                let tagged = tag_movie(&body, tags)?;
+                let size = header.len() as u64 + body_len;
+                let tagged_shift = shift + (make_box(b"moov", &tagged).len() as i64 - size as i64);
+                // Chunks are only moved by the movie box growing if they come after it.
+                let chunk_shift = if seen_data { shift } else { tagged_shift };
+                let tagged = match chunk_shift {
+                    0 => tagged,
+                    x => shift_chunk_offsets(&tagged, x)?,
+                };
+                output.write_all(&make_box(b"moov", &tagged))?;
+                shift = tagged_shift;
+                seen_movie = true;
+            }
+            b"moof" if shift != 0 => {
+                let body = read_body(&mut input, body_len)?;
+                let fragment = shift_fragment_offsets(&body, shift)?;
+                output.write_all(&make_box(b"moof", &fragment))?;
+            }
+            b"mfra" => {
+                io::copy(&mut (&mut input).take(body_len), &mut io::sink())?;
+                shift -= (header.len() as u64 + body_len) as i64;
+            }
+            _ => {
+                seen_data |= &kind == b"mdat";
+                output.write_all(&header)?;
+                let copied = io::copy(&mut (&mut input).take(body_len), &mut output)?;
+                if copied != body_len {
+                    return Err("MP4 file ended inside a box.".into());
+                }
+            }
+        }
+    }
+    if !seen_movie {
+        return Err("MP4 file contained no movie box to write tags to.".into());
+    }
+    output.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags() -> Vec<(String, String)> {
+        [
+            ("title", "Episode"),
+            ("season_number", "2"),
+            ("track", "5"),
+            ("network", "DR"),
+            ("unknown", "left out"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_owned(), v.to_owned()))
+        .collect()
+    }
+
+    fn item<'a>(user_data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+        let udta = boxes(user_data).unwrap().remove(0);
+        let meta = boxes(udta.body())
+            .unwrap()
+            .into_iter()
+            .find(|x| &x.kind == b"meta")
+            .unwrap();
+        let ilst = boxes(&meta.body()[4..])
+            .unwrap()
+            .into_iter()
+            .find(|x| &x.kind == b"ilst")
+            .unwrap();
+        let item = boxes(ilst.body())
+            .unwrap()
+            .into_iter()
+            .find(|x| &x.kind == kind)?;
+        let data = boxes(item.body()).unwrap().remove(0);
+        Some(&data.body()[8..])
+    }
+
+    #[test]
+    fn user_data_box_writes_tags_as_ilst_items() {
+        let user_data = user_data_box(&tags());
+        assert_eq!(item(&user_data, b"\xa9nam"), Some(&b"Episode"[..]));
+        assert_eq!(item(&user_data, b"tvsn"), Some(&2i32.to_be_bytes()[..]));
+        assert_eq!(
+            item(&user_data, b"trkn"),
+            Some(&[0, 0, 0, 5, 0, 0, 0, 0][..])
+        );
+        assert_eq!(item(&user_data, b"tvnt"), Some(&b"DR"[..]));
+        assert_eq!(item(&user_data, b"\xa9alb"), None);
+    }
+
+    #[test]
+    fn write_tags_shifts_offsets_after_the_movie_box() {
+        let path = std::env::temp_dir().join(format!("dr-downloader-tags-{}", std::process::id()));
+        let tagged_path = path.with_extension("tagged");
+        // A movie box with a chunk offset pointing into the media data after it,
+        // and a fragment with a base data offset pointing there as well.
+        let mut chunk_offsets = 1u32.to_be_bytes().to_vec();
+        chunk_offsets.extend(0u32.to_be_bytes());
+        let stbl = make_box(b"stbl", &make_full_box(b"stco", 0, 0, &chunk_offsets));
+        let trak = make_box(b"trak", &make_box(b"mdia", &make_box(b"minf", &stbl)));
+        let old_user_data = make_box(b"udta", &make_box(b"name", b"kept"));
+        let movie = make_box(b"moov", &[trak, old_user_data].concat());
+        let mut header = 1u32.to_be_bytes().to_vec();
+        header.extend(0u64.to_be_bytes());
+        let fragment = make_box(
+            b"moof",
+            &make_box(b"traf", &make_full_box(b"tfhd", 0, 1, &header)),
+        );
+        let ftyp = make_box(b"ftyp", b"isom\0\0\x02\0");
+        let data_offset = (ftyp.len() + movie.len() + fragment.len()) as u64;
+        let mut file = [ftyp.clone(), movie, fragment, make_box(b"mdat", b"media")].concat();
+        let stco = file.windows(4).position(|x| x == b"stco").unwrap();
+        file[stco + 12..stco + 16].copy_from_slice(&(data_offset as u32).to_be_bytes());
+        let tfhd = file.windows(4).position(|x| x == b"tfhd").unwrap();
+        file[tfhd + 12..tfhd + 20].copy_from_slice(&data_offset.to_be_bytes());
+        file.extend(make_box(b"mfra", b"index"));
+        std::fs::write(&path, &file).unwrap();
+
+        write_tags(&path, &tagged_path, &tags()).unwrap();
+        let tagged = std::fs::read(&tagged_path).unwrap();
+        std::fs::remove_file(&path).ok();
+        std::fs::remove_file(&tagged_path).ok();
+
+        let top = boxes(&tagged).unwrap();
+        let kinds = top.iter().map(|x| &x.kind).collect::<Vec<_>>();
+        assert_eq!(kinds, [b"ftyp", b"moov", b"moof", b"mdat"]);
+        let data_offset = (top[0].data.len() + top[1].data.len() + top[2].data.len()) as u64;
+        assert_eq!(&tagged[data_offset as usize + 8..], b"media");
+        let stco = tagged.windows(4).position(|x| x == b"stco").unwrap();
+        assert_eq!(
+            tagged[stco + 12..stco + 16],
+            (data_offset as u32).to_be_bytes()
+        );
+        let tfhd = tagged.windows(4).position(|x| x == b"tfhd").unwrap();
+        assert_eq!(tagged[tfhd + 12..tfhd + 20], data_offset.to_be_bytes());
+        let user_data = boxes(top[1].body()).unwrap().pop().unwrap();
+        let children = boxes(user_data.body()).unwrap();
+        assert_eq!(&children[0].kind, b"name");
+        assert_eq!(&children[1].kind, b"meta");
+        assert_eq!(item(user_data.data, b"\xa9nam"), Some(&b"Episode"[..]));
+    }
+}
@@ -0,0 +1,97 @@
+use crate::models::episode::{EpisodeInfo, NETWORK};
+
+/// The declaration NFO files start with. The Kodi format is read by Plex and Jellyfin as well.
+const HEADER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\" ?>\n";
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters are not allowed in XML 1.0.
+            x if x.is_control() && !matches!(x, '\n' | '\t') => (),
+            x => escaped.push(x),
+        }
+    }
+    escaped
+}
+
+/// Builds an XML document of a root element with text elements.
+struct Document {
+    root: &'static str,
+    xml: String,
+}
+
+impl Document {
+    fn new(root: &'static str) -> Self {
+        Document {
+            root,
+            xml: format!("{}<{}>\n", HEADER, root),
+        }
+    }
+
+    fn element(&mut self, name: &str, value: impl AsRef<str>) {
+        self.xml
+            .push_str(&format!("  <{0}>{1}</{0}>\n", name, escape(value.as_ref())));
+    }
+
+    fn optional(&mut self, name: &str, value: Option<impl AsRef<str>>) {
+        if let Some(value) = value {
+            self.element(name, value);
+        }
+    }
+
+    fn unique_id(&mut self, id: &str) {
+        self.xml.push_str(&format!(
+            "  <uniqueid type=\"dr\" default=\"true\">{}</uniqueid>\n",
+            escape(id)
+        ));
+    }
+
+    fn finish(mut self) -> String {
+        self.xml.push_str(&format!("</{}>\n", self.root));
+        self.xml
+    }
+}
+
+/// Get the tvshow.nfo of the show episode belongs to.
+pub(crate) fn tvshow(episode: &EpisodeInfo) -> String {
+    let meta = &episode.metadata;
+    let mut doc = Document::new("tvshow");
+    doc.element("title", meta.show.as_deref().unwrap_or(episode.title()));
+    doc.optional("plot", meta.show_description.as_ref());
+    for genre in &meta.genres {
+        doc.element("genre", genre);
+    }
+    doc.element("studio", NETWORK);
+    if let Some(id) = &meta.show_id {
+        doc.unique_id(id);
+    }
+    doc.finish()
+}
+
+/// Get the NFO of episode, which is named like its media file.
+pub(crate) fn episode(episode: &EpisodeInfo) -> String {
+    let meta = &episode.metadata;
+    let mut doc = Document::new("episodedetails");
+    doc.element("title", episode.title());
+    doc.optional("showtitle", meta.show.as_ref());
+    doc.optional("season", meta.season.map(|x| x.to_string()));
+    doc.optional("episode", meta.episode.map(|x| x.to_string()));
+    doc.optional("plot", meta.description.as_ref());
+    // Only full dates are aired dates, some episodes only have a year.
+    let date = meta.air_date.as_ref().filter(|x| x.len() == 10);
+    doc.optional("aired", date);
+    doc.optional("premiered", date);
+    doc.optional("year", meta.air_date.as_ref().and_then(|x| x.get(..4)));
+    for genre in &meta.genres {
+        doc.element("genre", genre);
+    }
+    doc.element("studio", NETWORK);
+    doc.unique_id(&episode.id);
+    doc.finish()
+}
@@ -0,0 +1,292 @@
+use crate::error::Result;
+use crate::ffmpeg::FfmpegCapabilities;
+use crate::format::Format;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VideoCodec {
+    /// Keep the video stream as is.
+    Copy,
+    H264,
+    H265,
+    Av1,
+    /// Leave out the video stream.
+    Disabled,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioCodec {
+    /// Keep the audio stream as is.
+    Copy,
+    Aac,
+    Opus,
+    Mp3,
+    /// Leave out the audio stream.
+    Disabled,
+}
+
+/// The codecs of the streams of an input, which copying keeps.
+/// None is a codec that is not known here, which is left to FFMPEG to check.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourceCodecs {
+    pub video: Option<VideoCodec>,
+    pub audio: Option<AudioCodec>,
+}
+
+impl Default for SourceCodecs {
+    /// DR streams H.264 video and AAC audio, which is assumed when a stream does not list its codecs.
+    fn default() -> Self {
+        SourceCodecs {
+            video: Some(VideoCodec::H264),
+            audio: Some(AudioCodec::Aac),
+        }
+    }
+}
+
+impl SourceCodecs {
+    /// Parse the CODECS attribute of an HLS variant, such as "avc1.64001f,mp4a.40.2".
+    /// A stream without a video or audio codec in the list is taken to have no such stream.
+    pub fn parse(codecs: &str) -> Self {
+        let mut source = SourceCodecs {
+            video: Some(VideoCodec::Disabled),
+            audio: Some(AudioCodec::Disabled),
+        };
+        for codec in codecs.split(',').map(|x| x.trim().to_ascii_lowercase()) {
+            match codec.split('.').next().unwrap_or_default() {
+                "avc1" | "avc3" => source.video = Some(VideoCodec::H264),
+                "hvc1" | "hev1" => source.video = Some(VideoCodec::H265),
+                "av01" => source.video = Some(VideoCodec::Av1),
+                "vp08" | "vp09" | "dvh1" | "dvhe" => source.video = None,
+                // MPEG-4 object types 0x6B and 0x69, and 40.34, are MP3 rather than AAC.
+                "mp4a" if matches!(codec.as_str(), "mp4a.6b" | "mp4a.69" | "mp4a.40.34") => {
+                    source.audio = Some(AudioCodec::Mp3)
+                }
+                "mp4a" => source.audio = Some(AudioCodec::Aac),
+                "opus" => source.audio = Some(AudioCodec::Opus),
+                "ac-3" | "ec-3" | "flac" => source.audio = None,
+                _ => (),
+            }
+        }
+        source
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VideoQuality {
+    /// Constant rate factor, lower is better.
+    Crf(u8),
+    /// Average bitrate in kbit/s.
+    Bitrate(u32),
+}
+
+impl VideoCodec {
+    /// Get the name of the FFMPEG encoder used for this codec, if it is re-encoded.
+    pub fn encoder(&self) -> Option<&'static str> {
+        match self {
+            VideoCodec::H264 => Some("libx264"),
+            VideoCodec::H265 => Some("libx265"),
+            VideoCodec::Av1 => Some("libsvtav1"),
+            VideoCodec::Copy | VideoCodec::Disabled => None,
+        }
+    }
+}
+
+impl AudioCodec {
+    /// Get the name of the FFMPEG encoder used for this codec, if it is re-encoded.
+    pub fn encoder(&self) -> Option<&'static str> {
+        match self {
+            AudioCodec::Aac => Some("aac"),
+            AudioCodec::Opus => Some("libopus"),
+            AudioCodec::Mp3 => Some("libmp3lame"),
+            AudioCodec::Copy | AudioCodec::Disabled => None,
+        }
+    }
+}
+
+/// How the Converter encodes its output. The default copies the streams without re-encoding.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConversionProfile {
+    video_codec: VideoCodec,
+    video_quality: Option<VideoQuality>,
+    max_height: Option<u32>,
+    audio_codec: AudioCodec,
+    audio_bitrate: Option<u32>,
+    extra_args: Vec<String>,
+}
+
+impl Default for ConversionProfile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConversionProfile {
+    /// Create a profile that copies the streams without re-encoding.
+    pub fn new() -> Self {
+        ConversionProfile {
+            video_codec: VideoCodec::Copy,
+            video_quality: None,
+            max_height: None,
+            audio_codec: AudioCodec::Copy,
+            audio_bitrate: None,
+            extra_args: vec![],
+        }
+    }
+
+    /// Smaller files for long term storage, without visible loss in quality.
+    pub fn archive() -> Self {
+        Self::new()
+            .with_video_codec(VideoCodec::H265)
+            .with_video_quality(VideoQuality::Crf(24))
+            .with_audio_codec(AudioCodec::Aac)
+            .with_audio_bitrate(128)
+    }
+
+    /// Small files for phones and tablets.
+    pub fn mobile() -> Self {
+        Self::new()
+            .with_video_codec(VideoCodec::H264)
+            .with_video_quality(VideoQuality::Crf(26))
+            .with_max_height(720)
+            .with_audio_codec(AudioCodec::Aac)
+            .with_audio_bitrate(96)
+    }
+
+    /// Only the audio, for programmes that are listened to rather than watched.
+    pub fn audio_only() -> Self {
+        Self::new()
+            .with_video_codec(VideoCodec::Disabled)
+            .with_audio_codec(AudioCodec::Aac)
+            .with_audio_bitrate(128)
+    }
+
+    /// Only the audio, encoded to suit the container of format.
+    /// AAC audio is copied as is into other containers than MP3, Ogg and WebM.
+    pub fn for_audio_format(format: &Format) -> Self {
+        let profile = Self::new().with_video_codec(VideoCodec::Disabled);
+        match format {
+            Format::Mp3 => profile
+                .with_audio_codec(AudioCodec::Mp3)
+                .with_audio_bitrate(128),
+            Format::Opus | Format::Ogg | Format::Webm => profile
+                .with_audio_codec(AudioCodec::Opus)
+                .with_audio_bitrate(96),
+            _ => profile,
+        }
+    }
+
+    pub fn with_video_codec(mut self, codec: VideoCodec) -> Self {
+        self.video_codec = codec;
+        self
+    }
+
+    pub fn with_video_quality(mut self, quality: VideoQuality) -> Self {
+        self.video_quality = Some(quality);
+        self
+    }
+
+    /// Scale the video down to at most height pixels, keeping the aspect ratio. Requires re-encoding the video.
+    pub fn with_max_height(mut self, height: u32) -> Self {
+        self.max_height = Some(height);
+        self
+    }
+
+    pub fn with_audio_codec(mut self, codec: AudioCodec) -> Self {
+        self.audio_codec = codec;
+        self
+    }
+
+    /// Set the audio bitrate in kbit/s.
+    pub fn with_audio_bitrate(mut self, bitrate: u32) -> Self {
+        self.audio_bitrate = Some(bitrate);
+        self
+    }
+
+    /// Add arguments passed to FFMPEG right before the output.
+    pub fn with_extra_args<S: Into<String>>(mut self, args: impl IntoIterator<Item = S>) -> Self {
+        self.extra_args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn get_video_codec(&self) -> VideoCodec {
+        self.video_codec
+    }
+
+    pub fn get_audio_codec(&self) -> AudioCodec {
+        self.audio_codec
+    }
+
+    /// Check that the profile is consistent, and that FFMPEG has its encoders if capabilities are given.
+    pub fn verify(&self, capabilities: Option<&FfmpegCapabilities>) -> Result<()> {
+        let encodes_video = self.video_codec.encoder().is_some();
+        if !encodes_video && (self.max_height.is_some() || self.video_quality.is_some()) {
+            return Err("Video quality and max height require re-encoding the video.".into());
+        }
+        if self.audio_codec.encoder().is_none() && self.audio_bitrate.is_some() {
+            return Err("Audio bitrate requires re-encoding the audio.".into());
+        }
+        if self.video_codec == VideoCodec::Disabled && self.audio_codec == AudioCodec::Disabled {
+            return Err("Profile leaves out both video and audio.".into());
+        }
+        let capabilities = match capabilities {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        let encoders = [self.video_codec.encoder(), self.audio_codec.encoder()];
+        for encoder in encoders.into_iter().flatten() {
+            if !capabilities.has_encoder(encoder) {
+                return Err(format!(
+                    "FFmpeg {} does not have the {} encoder.",
+                    capabilities.version.name, encoder
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the FFMPEG arguments for the codecs of the output.
+    /// Leaving out streams is up to the caller, which maps the streams of the input.
+    /// Video options only apply to the first video stream, so cover art added after it is left alone.
+    pub(crate) fn to_args(&self) -> Vec<String> {
+        // Copy by default so any other streams are kept as they are.
+        let mut args = vec!["-c".to_owned(), "copy".to_owned()];
+        match self.video_codec {
+            VideoCodec::Disabled | VideoCodec::Copy => (),
+            codec => {
+                args.extend([
+                    "-c:v:0".to_owned(),
+                    codec.encoder().unwrap_or("copy").to_owned(),
+                ]);
+                match self.video_quality {
+                    Some(VideoQuality::Crf(crf)) => {
+                        args.extend(["-crf:v:0".to_owned(), crf.to_string()])
+                    }
+                    Some(VideoQuality::Bitrate(kbps)) => {
+                        args.extend(["-b:v:0".to_owned(), format!("{}k", kbps)])
+                    }
+                    None => (),
+                }
+                if let Some(height) = self.max_height {
+                    args.extend([
+                        "-filter:v:0".to_owned(),
+                        format!("scale=-2:'min(ih,{})'", height),
+                    ]);
+                }
+            }
+        }
+        match self.audio_codec {
+            AudioCodec::Disabled | AudioCodec::Copy => (),
+            codec => {
+                args.extend([
+                    "-c:a".to_owned(),
+                    codec.encoder().unwrap_or("copy").to_owned(),
+                ]);
+                if let Some(kbps) = self.audio_bitrate {
+                    args.extend(["-b:a".to_owned(), format!("{}k", kbps)]);
+                }
+            }
+        }
+        args.extend(self.extra_args.iter().cloned());
+        args
+    }
+}
@@ -0,0 +1,213 @@
+use std::time::{Duration, Instant};
+
+/// Download progress of a single episode.
+#[derive(Clone, Debug)]
+pub struct Progress {
+    pub bytes: u64,
+    pub segments_done: usize,
+    pub segments_total: usize,
+    /// Download speed in bytes per second.
+    pub speed: f64,
+    pub estimated_size: Option<u64>,
+    pub eta: Option<Duration>,
+}
+
+/// Conversion progress as reported by FFMPEG.
+#[derive(Clone, Debug, Default)]
+pub struct ConvertProgress {
+    /// How far into the media FFMPEG has written.
+    pub out_time: Duration,
+    pub total_size: u64,
+    /// Conversion speed as a multiple of realtime.
+    pub speed: Option<f64>,
+    /// Duration of the media being converted, if known.
+    pub duration: Option<Duration>,
+    /// How much of the media has been converted, from 0 to 100.
+    pub percentage: Option<f64>,
+    pub done: bool,
+}
+
+/// Tracks the speed of a download that may have been resumed from a previous run.
+pub(crate) struct ProgressTracker {
+    segments_total: usize,
+    start: Instant,
+    start_bytes: u64,
+}
+
+impl ProgressTracker {
+    pub fn new(segments_total: usize, start_bytes: u64) -> Self {
+        ProgressTracker {
+            segments_total,
+            start: Instant::now(),
+            start_bytes,
+        }
+    }
+
+    pub fn update(&self, bytes: u64, segments_done: usize) -> Progress {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let speed = match elapsed > 0.0 {
+            true => (bytes - self.start_bytes) as f64 / elapsed,
+            false => 0.0,
+        };
+        let estimated_size = match segments_done {
+            0 => None,
+            _ => Some(bytes / segments_done as u64 * self.segments_total as u64),
+        };
+        let eta = estimated_size
+            .filter(|_| speed > 0.0)
+            .and_then(|x| Duration::try_from_secs_f64(x.saturating_sub(bytes) as f64 / speed).ok());
+        Progress {
+            bytes,
+            segments_done,
+            segments_total: self.segments_total,
+            speed,
+            estimated_size,
+            eta,
+        }
+    }
+}
+
+/// Parse an FFMPEG timestamp in the form HH:MM:SS.micro.
+pub(crate) fn parse_timestamp(timestamp: &str) -> Option<Duration> {
+    let mut parts = timestamp.trim().splitn(3, ':');
+    let hours = parts.next()?.parse::<u64>().ok()?;
+    let minutes = parts.next()?.parse::<u64>().ok()?;
+    let seconds = parts.next()?.parse::<f64>().ok()?;
+    if !seconds.is_finite() || seconds < 0.0 {
+        return None;
+    }
+    let seconds = Duration::try_from_secs_f64(seconds).ok()?;
+    let whole = hours
+        .checked_mul(3600)?
+        .checked_add(minutes.checked_mul(60)?)?;
+    Duration::from_secs(whole).checked_add(seconds)
+}
+
+/// Parse the input duration from a line of FFMPEG log output, such as "  Duration: 00:42:10.52, start: ...".
+pub(crate) fn parse_log_duration(line: &str) -> Option<Duration> {
+    let rest = line.trim_start().strip_prefix("Duration:")?;
+    let end = rest.find(',').unwrap_or(rest.len());
+    parse_timestamp(&rest[..end])
+}
+
+impl ConvertProgress {
+    fn update_percentage(&mut self) {
+        self.percentage = match self.done {
+            true => Some(100.0),
+            false => self
+                .duration
+                .filter(|x| !x.is_zero())
+                .map(|x| (self.out_time.as_secs_f64() / x.as_secs_f64() * 100.0).clamp(0.0, 100.0)),
+        };
+    }
+
+    /// Apply a key=value line of FFMPEG -progress output.
+    /// Returns true when the line ends a progress block.
+    pub(crate) fn apply_line(&mut self, line: &str) -> bool {
+        let (key, value) = match line.trim().split_once('=') {
+            Some(x) => x,
+            None => return false,
+        };
+        match key {
+            // out_time_ms is in microseconds as well, FFMPEG has always reported it wrongly.
+            "out_time_us" | "out_time_ms" => {
+                if let Ok(us) = value.parse::<u64>() {
+                    self.out_time = Duration::from_micros(us);
+                }
+            }
+            "out_time" => {
+                if let Some(time) = parse_timestamp(value) {
+                    self.out_time = time;
+                }
+            }
+            "total_size" => self.total_size = value.parse().unwrap_or(self.total_size),
+            "speed" => self.speed = value.trim_end_matches('x').trim().parse().ok(),
+            "progress" => {
+                self.done = value == "end";
+                self.update_percentage();
+                return true;
+            }
+            _ => (),
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_line_reads_progress_blocks() {
+        let mut progress = ConvertProgress::default();
+        assert!(!progress.apply_line("out_time_us=1500000"));
+        assert!(!progress.apply_line("total_size=2048"));
+        assert!(!progress.apply_line("speed=2.5x"));
+        assert!(progress.apply_line("progress=continue"));
+        assert_eq!(progress.out_time, Duration::from_millis(1500));
+        assert_eq!(progress.total_size, 2048);
+        assert_eq!(progress.speed, Some(2.5));
+        assert!(!progress.done);
+        assert!(progress.apply_line("progress=end"));
+        assert!(progress.done);
+    }
+
+    #[test]
+    fn apply_line_ignores_unknown_and_invalid_lines() {
+        let mut progress = ConvertProgress::default();
+        assert!(!progress.apply_line("bitrate=1000kbits/s"));
+        assert!(!progress.apply_line("not a progress line"));
+        assert!(!progress.apply_line("out_time_us=N/A"));
+        assert!(!progress.apply_line("speed=N/A"));
+        assert_eq!(progress.out_time, Duration::ZERO);
+        assert_eq!(progress.speed, None);
+    }
+
+    #[test]
+    fn parse_timestamp_reads_ffmpeg_timestamps() {
+        assert_eq!(
+            parse_timestamp("01:02:03.500000"),
+            Some(Duration::from_millis(3_723_500))
+        );
+        assert_eq!(parse_timestamp(" 00:00:00 "), Some(Duration::ZERO));
+        assert_eq!(
+            parse_log_duration("  Duration: 00:42:10.52, start: 0.000000, bitrate: 5000 kb/s"),
+            Some(Duration::from_millis(2_530_520))
+        );
+        assert_eq!(parse_log_duration("  Stream #0:0: Video: h264"), None);
+    }
+
+    #[test]
+    fn parse_timestamp_rejects_invalid_seconds() {
+        for timestamp in [
+            "00:00:-1",
+            "00:00:NaN",
+            "00:00:inf",
+            "00:00",
+            "N/A",
+            "-01:00:00",
+            "18446744073709551615:00:00",
+            "00:18446744073709551615:00",
+            "5124095576030431:00:16",
+            "00:00:1e300",
+        ] {
+            assert_eq!(parse_timestamp(timestamp), None, "{}", timestamp);
+        }
+    }
+
+    #[test]
+    fn apply_line_reports_percentage() {
+        let mut progress = ConvertProgress {
+            duration: Some(Duration::from_secs(100)),
+            ..Default::default()
+        };
+        progress.apply_line("out_time=00:00:25.000000");
+        progress.apply_line("progress=continue");
+        assert_eq!(progress.out_time, Duration::from_secs(25));
+        assert_eq!(progress.percentage, Some(25.0));
+        progress.apply_line("out_time=N/A");
+        assert_eq!(progress.out_time, Duration::from_secs(25));
+        progress.apply_line("progress=end");
+        assert_eq!(progress.percentage, Some(100.0));
+    }
+}
@@ -0,0 +1,694 @@
+use crate::batch::BatchItem;
+use crate::error::{OkOrGeneric, Result};
+use crate::format::Format;
+use crate::job::JobHandle;
+use crate::report::SaveReport;
+use crate::saver::Saver;
+use futures::Stream;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{broadcast, Mutex, Notify};
+use tracing::Instrument;
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+const UPDATE_CHANNEL_CAPACITY: usize = 64;
+
+pub type QueueJobId = u64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueueJobStatus {
+    Queued,
+    Running,
+    Done,
+    /// Failed on its last attempt.
+    Failed,
+    Cancelled,
+}
+
+impl QueueJobStatus {
+    fn name(self) -> &'static str {
+        match self {
+            QueueJobStatus::Queued => "queued",
+            QueueJobStatus::Running => "running",
+            QueueJobStatus::Done => "done",
+            QueueJobStatus::Failed => "failed",
+            QueueJobStatus::Cancelled => "cancelled",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [
+            QueueJobStatus::Queued,
+            QueueJobStatus::Running,
+            QueueJobStatus::Done,
+            QueueJobStatus::Failed,
+            QueueJobStatus::Cancelled,
+        ]
+        .into_iter()
+        .find(|x| x.name() == name)
+    }
+}
+
+/// A job in a JobQueue.
+#[derive(Clone, Debug)]
+pub struct QueueJob {
+    pub id: QueueJobId,
+    pub item: BatchItem,
+    /// Jobs with a higher priority run first, and jobs of the same priority in the order they were added.
+    pub priority: i32,
+    pub status: QueueJobStatus,
+    /// How many times the job has been started.
+    pub attempts: u32,
+    /// The error of the last attempt, if it failed.
+    pub error: Option<String>,
+    /// The report of the last attempt.
+    pub report: Option<SaveReport>,
+}
+
+impl QueueJob {
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "url": self.item.url,
+            "out_dir": self.item.out_dir,
+            "format": self.item.format.as_ref().map(Format::to_json),
+            "priority": self.priority,
+            "status": self.status.name(),
+            "attempts": self.attempts,
+            "error": self.error,
+            "report": self.report.as_ref().map(SaveReport::to_json),
+        })
+    }
+
+    fn parse(json: &Value) -> Result<Self> {
+        let format = match &json["format"] {
+            Value::Null => None,
+            x => Some(Format::parse(x)?),
+        };
+        let item = BatchItem {
+            url: json["url"]
+                .as_str()
+                .ok_or_generic("Could not get the url of a queued job.")?
+                .to_owned(),
+            out_dir: json["out_dir"].as_str().map(str::to_owned),
+            format,
+        };
+        Ok(QueueJob {
+            id: json["id"]
+                .as_u64()
+                .ok_or_generic("Could not get the id of a queued job.")?,
+            item,
+            priority: match &json["priority"] {
+                Value::Null => 0,
+                x => x
+                    .as_i64()
+                    .and_then(|x| i32::try_from(x).ok())
+                    .ok_or_generic("Could not get the priority of a queued job.")?,
+            },
+            status: json["status"]
+                .as_str()
+                .and_then(QueueJobStatus::from_name)
+                .ok_or_generic("Could not get the status of a queued job.")?,
+            attempts: json["attempts"].as_u64().unwrap_or(0) as u32,
+            error: json["error"].as_str().map(str::to_owned),
+            report: match &json["report"] {
+                Value::Null => None,
+                x => Some(SaveReport::parse(x)?),
+            },
+        })
+    }
+}
+
+struct QueueInner {
+    jobs: BTreeMap<QueueJobId, QueueJob>,
+    next_id: QueueJobId,
+    journal: tokio::fs::File,
+    /// The handles of the running jobs, to cancel them by id.
+    running: HashMap<QueueJobId, JobHandle>,
+    updates: broadcast::Sender<QueueJob>,
+}
+
+impl QueueInner {
+    /// Append a record to the journal, and wait until it is on disk.
+    async fn append(&mut self, record: Value) -> Result<()> {
+        let mut line = record.to_string();
+        line.push('\n');
+        self.journal.write_all(line.as_bytes()).await?;
+        self.journal.sync_data().await?;
+        Ok(())
+    }
+
+    async fn update(&mut self, job: QueueJob) -> Result<()> {
+        self.append(json!({ "job": job.to_json() })).await?;
+        // Sending only fails when no stream is listening.
+        self.updates.send(job.clone()).ok();
+        self.jobs.insert(job.id, job);
+        Ok(())
+    }
+
+    /// Get the queued job to run next.
+    fn next(&self) -> Option<&QueueJob> {
+        self.jobs
+            .values()
+            .filter(|x| x.status == QueueJobStatus::Queued)
+            .min_by_key(|x| (-(x.priority as i64), x.id))
+    }
+}
+
+/// A queue of save jobs kept in a journal file, so it survives restarts.
+///
+/// Every change is appended to the journal and synced before it takes effect. When a queue is opened the journal is
+/// replayed and compacted, and jobs that were running when the process stopped are queued again, to resume from their
+/// partial output, unless it was their last attempt. Clones share the same queue, and a journal is only open in one queue at a time.
+#[derive(Clone)]
+pub struct JobQueue {
+    path: PathBuf,
+    max_attempts: u32,
+    inner: Arc<Mutex<QueueInner>>,
+    added: Arc<Notify>,
+    /// Held for the lock on the journal, which is released when the last clone is dropped.
+    _lock: Arc<std::fs::File>,
+}
+
+impl JobQueue {
+    /// Open the queue with the journal at path, creating it if it does not exist.
+    /// Jobs are started at most 3 times before they are failed, see open_with_max_attempts.
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open_with_max_attempts(path, DEFAULT_MAX_ATTEMPTS).await
+    }
+
+    /// Open the queue like open, starting each job at most max_attempts times before it is failed.
+    /// A job that was running when the process stopped is failed if that was its last attempt, so a job that
+    /// crashes the process is not started forever.
+    /// The journal is locked through a .lock file next to it while the queue is open, and an error is returned if
+    /// it is already locked, such as by another process.
+    pub async fn open_with_max_attempts(path: impl AsRef<Path>, max_attempts: u32) -> Result<Self> {
+        let path = path.as_ref().to_owned();
+        let max_attempts = max_attempts.max(1);
+        let lock = Self::lock(&path)?;
+        let text = match tokio::fs::read_to_string(&path).await {
+            Ok(x) => x,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let (mut jobs, next_id) = Self::replay(&text)?;
+        for job in jobs.values_mut() {
+            if job.status != QueueJobStatus::Running {
+                continue;
+            }
+            if job.attempts >= max_attempts {
+                tracing::warn!(id = job.id, url = %job.item.url, "failing job interrupted on its last attempt");
+                job.status = QueueJobStatus::Failed;
+                job.error = Some(format!(
+                    "Interrupted on attempt {} of {}.",
+                    job.attempts, max_attempts
+                ));
+            } else {
+                tracing::info!(id = job.id, url = %job.item.url, "requeueing interrupted job");
+                job.status = QueueJobStatus::Queued;
+            }
+        }
+        let journal = Self::compact(&path, &jobs, next_id).await?;
+        Ok(JobQueue {
+            path,
+            max_attempts,
+            inner: Arc::new(Mutex::new(QueueInner {
+                jobs,
+                next_id,
+                journal,
+                running: HashMap::new(),
+                updates: broadcast::channel(UPDATE_CHANNEL_CAPACITY).0,
+            })),
+            added: Arc::new(Notify::new()),
+            _lock: Arc::new(lock),
+        })
+    }
+
+    /// Lock the journal at path through the .lock file next to it, which stays locked until the file is closed.
+    fn lock(path: &Path) -> Result<std::fs::File> {
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)?;
+        match file.try_lock() {
+            Ok(()) => Ok(file),
+            Err(std::fs::TryLockError::WouldBlock) => Err(format!(
+                "The queue journal {} is already open, {} is locked.",
+                path.display(),
+                Path::new(&lock_path).display()
+            )
+            .into()),
+            Err(std::fs::TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the jobs of a journal, and the id of the next job added.
+    fn replay(text: &str) -> Result<(BTreeMap<QueueJobId, QueueJob>, QueueJobId)> {
+        let mut jobs = BTreeMap::new();
+        let mut next_id = 1;
+        let lines = text
+            .lines()
+            .filter(|x| !x.trim().is_empty())
+            .collect::<Vec<_>>();
+        for (i, line) in lines.iter().enumerate() {
+            let record: Value = match serde_json::from_str(line) {
+                Ok(x) => x,
+                // The last record is cut short if the process stopped while writing it.
+                Err(_) if i + 1 == lines.len() => {
+                    tracing::warn!("ignoring incomplete record at the end of the queue journal");
+                    break;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            if let Some(id) = record["next_id"].as_u64() {
+                next_id = next_id.max(id);
+            } else if let Some(id) = record["removed"].as_u64() {
+                jobs.remove(&id);
+            } else {
+                let job = QueueJob::parse(&record["job"])?;
+                next_id = next_id.max(job.id + 1);
+                jobs.insert(job.id, job);
+            }
+        }
+        Ok((jobs, next_id))
+    }
+
+    /// Replace the journal at path with a record per job, returning it opened for appending.
+    /// The next id is kept as well, so the ids of removed jobs are not given out again.
+    async fn compact(
+        path: &Path,
+        jobs: &BTreeMap<QueueJobId, QueueJob>,
+        next_id: QueueJobId,
+    ) -> Result<tokio::fs::File> {
+        let mut text = json!({ "next_id": next_id }).to_string();
+        text.push('\n');
+        for job in jobs.values() {
+            text.push_str(&json!({ "job": job.to_json() }).to_string());
+            text.push('\n');
+        }
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let mut tmp = tokio::fs::File::create(&tmp_path).await?;
+        tmp.write_all(text.as_bytes()).await?;
+        tmp.sync_all().await?;
+        drop(tmp);
+        tokio::fs::rename(&tmp_path, path).await?;
+        Ok(tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .await?)
+    }
+
+    /// Add a job to save item, returning its id.
+    pub async fn enqueue(&self, item: impl Into<BatchItem>, priority: i32) -> Result<QueueJobId> {
+        let mut inner = self.inner.lock().await;
+        let job = QueueJob {
+            id: inner.next_id,
+            item: item.into(),
+            priority,
+            status: QueueJobStatus::Queued,
+            attempts: 0,
+            error: None,
+            report: None,
+        };
+        let id = job.id;
+        inner.update(job).await?;
+        inner.next_id += 1;
+        drop(inner);
+        self.added.notify_one();
+        Ok(id)
+    }
+
+    pub async fn get(&self, id: QueueJobId) -> Option<QueueJob> {
+        self.inner.lock().await.jobs.get(&id).cloned()
+    }
+
+    /// Get every job, in the order they were added.
+    pub async fn jobs(&self) -> Vec<QueueJob> {
+        self.inner.lock().await.jobs.values().cloned().collect()
+    }
+
+    /// Get a stream of the jobs of this queue as they change. Removed jobs are not sent.
+    /// Updates are dropped for streams that fall too far behind.
+    pub async fn update_stream(&self) -> impl Stream<Item = QueueJob> + Send + 'static {
+        let rx = self.inner.lock().await.updates.subscribe();
+        futures::stream::unfold(rx, |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(job) => return Some((job, rx)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+
+    /// Cancel a job. Queued jobs are not run, and running jobs are stopped as if their save was cancelled.
+    pub async fn cancel(&self, id: QueueJobId) -> Result<()> {
+        let mut inner = self.inner.lock().await;
+        let mut job = inner
+            .jobs
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| format!("There is no job {}.", id))?;
+        match job.status {
+            QueueJobStatus::Queued => {
+                job.status = QueueJobStatus::Cancelled;
+                inner.update(job).await?;
+            }
+            QueueJobStatus::Running => {
+                if let Some(handle) = inner.running.get(&id) {
+                    handle.cancel();
+                }
+            }
+            QueueJobStatus::Cancelled => (),
+            QueueJobStatus::Done | QueueJobStatus::Failed => {
+                return Err(format!("Job {} has already finished.", id).into())
+            }
+        }
+        Ok(())
+    }
+
+    /// Queue a failed or cancelled job again, with its attempts reset.
+    pub async fn retry(&self, id: QueueJobId) -> Result<()> {
+        let mut inner = self.inner.lock().await;
+        let mut job = inner
+            .jobs
+            .get(&id)
+            .filter(|x| matches!(x.status, QueueJobStatus::Failed | QueueJobStatus::Cancelled))
+            .cloned()
+            .ok_or_else(|| format!("Job {} is not a failed or cancelled job.", id))?;
+        job.status = QueueJobStatus::Queued;
+        job.attempts = 0;
+        inner.update(job).await?;
+        drop(inner);
+        self.added.notify_one();
+        Ok(())
+    }
+
+    /// Remove a job that is not running.
+    pub async fn remove(&self, id: QueueJobId) -> Result<()> {
+        let mut inner = self.inner.lock().await;
+        match inner.jobs.get(&id).map(|x| x.status) {
+            None => return Err(format!("There is no job {}.", id).into()),
+            Some(QueueJobStatus::Running) => return Err(format!("Job {} is running.", id).into()),
+            Some(_) => (),
+        }
+        inner.append(json!({ "removed": id })).await?;
+        inner.jobs.remove(&id);
+        Ok(())
+    }
+
+    /// Remove every job that is done.
+    pub async fn clear_done(&self) -> Result<()> {
+        let mut inner = self.inner.lock().await;
+        let done = inner
+            .jobs
+            .values()
+            .filter(|x| x.status == QueueJobStatus::Done)
+            .map(|x| x.id)
+            .collect::<Vec<_>>();
+        for id in done {
+            inner.append(json!({ "removed": id })).await?;
+            inner.jobs.remove(&id);
+        }
+        Ok(())
+    }
+
+    /// Mark the next queued job as running, returning it with the handle it is cancelled through.
+    async fn start_next(&self) -> Result<Option<(QueueJob, JobHandle)>> {
+        let mut inner = self.inner.lock().await;
+        let mut job = match inner.next() {
+            Some(x) => x.clone(),
+            None => return Ok(None),
+        };
+        job.status = QueueJobStatus::Running;
+        job.attempts += 1;
+        inner.update(job.clone()).await?;
+        let handle = JobHandle::new();
+        inner.running.insert(job.id, handle.clone());
+        Ok(Some((job, handle)))
+    }
+
+    async fn run_job(
+        &self,
+        mut job: QueueJob,
+        job_handle: JobHandle,
+        saver: &Saver,
+        out_dir: &str,
+        handle: &JobHandle,
+    ) -> Result<()> {
+        // The job has its own handle so it can be cancelled alone, which follows the handle of the runner.
+        let span = tracing::info_span!("queue_job", id = job.id, attempt = job.attempts);
+        let save = saver
+            .save_batch([job.item.clone()], out_dir, None, &job_handle)
+            .instrument(span);
+        tokio::pin!(save);
+        let report = tokio::select! {
+            report = &mut save => report,
+            _ = handle.forward_to(&job_handle) => save.await,
+        };
+        let mut inner = self.inner.lock().await;
+        inner.running.remove(&job.id);
+        let error = match &report {
+            _ if handle.is_cancelled() => {
+                // Interrupted rather than failed, so the attempt does not count.
+                job.status = QueueJobStatus::Queued;
+                job.attempts -= 1;
+                None
+            }
+            _ if job_handle.is_cancelled() => {
+                job.status = QueueJobStatus::Cancelled;
+                None
+            }
+            Ok(x) if x.is_success() => None,
+            Ok(x) => Some(match x.first_error() {
+                Some(e) if x.failed().count() == 1 => e.to_string(),
+                Some(e) => format!(
+                    "{} episodes failed, the first with: {}",
+                    x.failed().count(),
+                    e
+                ),
+                None => "An episode failed.".to_owned(),
+            }),
+            Err(e) => Some(e.to_string()),
+        };
+        match (&error, job.status) {
+            (_, QueueJobStatus::Queued | QueueJobStatus::Cancelled) => (),
+            (None, _) => job.status = QueueJobStatus::Done,
+            (Some(e), _) if job.attempts < self.max_attempts => {
+                tracing::warn!(id = job.id, attempt = job.attempts, error = %e, "queued job failed, retrying");
+                job.status = QueueJobStatus::Queued;
+            }
+            (Some(e), _) => {
+                tracing::warn!(id = job.id, error = %e, "queued job failed");
+                job.status = QueueJobStatus::Failed;
+            }
+        }
+        job.error = error;
+        job.report = report.ok();
+        inner.update(job).await
+    }
+
+    /// Run queued jobs with saver one at a time until none are left or handle is cancelled.
+    /// Jobs without their own output directory are saved to out_dir, in the default format of saver unless they have one.
+    /// Failed jobs are queued again until they reach the maximum attempts. Jobs that are interrupted by a cancel stay queued.
+    pub async fn run_pending(
+        &self,
+        saver: &Saver,
+        out_dir: impl AsRef<str>,
+        handle: &JobHandle,
+    ) -> Result<()> {
+        let out_dir = out_dir.as_ref();
+        while !handle.is_cancelled() {
+            let (job, job_handle) = match self.start_next().await? {
+                Some(x) => x,
+                None => break,
+            };
+            self.run_job(job, job_handle, saver, out_dir, handle)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Run queued jobs like run_pending, waiting for jobs to be added when the queue is empty, until handle is cancelled.
+    pub async fn run(
+        &self,
+        saver: &Saver,
+        out_dir: impl AsRef<str>,
+        handle: &JobHandle,
+    ) -> Result<()> {
+        let out_dir = out_dir.as_ref();
+        while !handle.is_cancelled() {
+            self.run_pending(saver, out_dir, handle).await?;
+            // A job added while running leaves a permit, so it is not missed.
+            tokio::select! {
+                _ = self.added.notified() => (),
+                _ = handle.cancelled() => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://www.dr.dk/drtv/episode/tv-avisen_67890";
+
+    fn job(id: QueueJobId, status: QueueJobStatus, attempts: u32) -> QueueJob {
+        QueueJob {
+            id,
+            item: BatchItem::new(URL),
+            priority: 0,
+            status,
+            attempts,
+            error: None,
+            report: None,
+        }
+    }
+
+    fn record(job: &QueueJob) -> String {
+        json!({ "job": job.to_json() }).to_string()
+    }
+
+    /// Get a journal path of its own in the temporary directory, without the files a previous run left.
+    fn journal_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "dr-downloader-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ));
+        remove_journal(&path);
+        path
+    }
+
+    fn remove_journal(path: &Path) {
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        std::fs::remove_file(path).ok();
+        std::fs::remove_file(lock_path).ok();
+    }
+
+    #[test]
+    fn job_json_round_trips() {
+        let mut original = job(7, QueueJobStatus::Failed, 2);
+        original.item = BatchItem::new(URL)
+            .with_out_dir("news")
+            .with_format(Format::Mkv);
+        original.priority = -3;
+        original.error = Some("Status code was not 200 OK.".to_owned());
+        let parsed = QueueJob::parse(&original.to_json()).unwrap();
+        assert_eq!(parsed.id, 7);
+        assert_eq!(parsed.item, original.item);
+        assert_eq!(parsed.priority, -3);
+        assert_eq!(parsed.status, QueueJobStatus::Failed);
+        assert_eq!(parsed.attempts, 2);
+        assert_eq!(parsed.error, original.error);
+        assert!(parsed.report.is_none());
+
+        original.item.format = Some(Format::custom(".flv", "flv"));
+        let parsed = QueueJob::parse(&original.to_json()).unwrap();
+        assert_eq!(parsed.item.format, original.item.format);
+    }
+
+    #[test]
+    fn job_parse_rejects_out_of_range_priorities() {
+        let with_priority = |priority: Value| {
+            let mut json = job(7, QueueJobStatus::Queued, 0).to_json();
+            json["priority"] = priority;
+            QueueJob::parse(&json)
+        };
+        assert_eq!(with_priority(Value::Null).unwrap().priority, 0);
+        assert_eq!(with_priority(json!(i32::MIN)).unwrap().priority, i32::MIN);
+        assert!(with_priority(json!(i32::MAX as i64 + 1)).is_err());
+        assert!(with_priority(json!(i32::MIN as i64 - 1)).is_err());
+        assert!(with_priority(json!("high")).is_err());
+    }
+
+    #[test]
+    fn job_json_keeps_the_report() {
+        let mut original = job(3, QueueJobStatus::Done, 1);
+        original.report = Some(SaveReport::default());
+        let parsed = QueueJob::parse(&original.to_json()).unwrap();
+        assert!(parsed.report.unwrap().episodes.is_empty());
+    }
+
+    #[test]
+    fn replay_applies_records_in_order() {
+        let lines = [
+            record(&job(1, QueueJobStatus::Queued, 0)),
+            record(&job(2, QueueJobStatus::Queued, 0)),
+            record(&job(1, QueueJobStatus::Done, 1)),
+            json!({ "removed": 2 }).to_string(),
+        ];
+        let (jobs, next_id) = JobQueue::replay(&lines.join("\n")).unwrap();
+        assert_eq!(jobs.keys().copied().collect::<Vec<_>>(), [1]);
+        assert_eq!(jobs[&1].status, QueueJobStatus::Done);
+        assert_eq!(jobs[&1].attempts, 1);
+        // The id of the removed job is not given out again.
+        assert_eq!(next_id, 3);
+        let (jobs, next_id) = JobQueue::replay(&json!({ "next_id": 10 }).to_string()).unwrap();
+        assert!(jobs.is_empty());
+        assert_eq!(next_id, 10);
+    }
+
+    #[test]
+    fn replay_ignores_only_a_cut_short_last_record() {
+        let complete = record(&job(1, QueueJobStatus::Queued, 0));
+        let cut = &record(&job(2, QueueJobStatus::Queued, 0))[..20];
+        let (jobs, next_id) = JobQueue::replay(&format!("{}\n{}", complete, cut)).unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(next_id, 2);
+        assert!(JobQueue::replay(&format!("{}\n{}", cut, complete)).is_err());
+    }
+
+    #[tokio::test]
+    async fn open_requeues_interrupted_jobs_until_their_last_attempt() {
+        let path = journal_path("interrupted");
+        let lines = [
+            record(&job(1, QueueJobStatus::Running, 1)),
+            record(&job(2, QueueJobStatus::Running, 3)),
+        ];
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        let queue = JobQueue::open_with_max_attempts(&path, 3).await.unwrap();
+        assert_eq!(queue.get(1).await.unwrap().status, QueueJobStatus::Queued);
+        let failed = queue.get(2).await.unwrap();
+        assert_eq!(failed.status, QueueJobStatus::Failed);
+        assert!(failed.error.is_some());
+        drop(queue);
+        // The journal was compacted with the new statuses.
+        let queue = JobQueue::open(&path).await.unwrap();
+        assert_eq!(queue.get(2).await.unwrap().status, QueueJobStatus::Failed);
+        drop(queue);
+        remove_journal(&path);
+    }
+
+    #[tokio::test]
+    async fn open_locks_the_journal() {
+        let path = journal_path("locked");
+        let queue = JobQueue::open(&path).await.unwrap();
+        let id = queue.enqueue(URL, 0).await.unwrap();
+        assert!(JobQueue::open(&path).await.is_err());
+        // Clones share the lock, which is released when the last one is dropped.
+        let clone = queue.clone();
+        drop(queue);
+        assert!(JobQueue::open(&path).await.is_err());
+        drop(clone);
+        let queue = JobQueue::open(&path).await.unwrap();
+        assert_eq!(queue.get(id).await.unwrap().item.url, URL);
+        drop(queue);
+        remove_journal(&path);
+    }
+}
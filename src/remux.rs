@@ -0,0 +1,1273 @@
+use crate::error::{Cancelled, Result};
+use crate::job::JobHandle;
+use crate::mp4::{self, make_box, make_full_box};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const PACKET_LEN: usize = 188;
+const SYNC_BYTE: u8 = 0x47;
+const STREAM_TYPE_AAC: u8 = 0x0F;
+const STREAM_TYPE_H264: u8 = 0x1B;
+/// The clock of transport stream timestamps, which is also the timescale of the video track.
+const TS_CLOCK: u64 = 90_000;
+const MOVIE_TIMESCALE: u64 = 1000;
+/// Timestamps wrap around after 33 bits.
+const TIMESTAMP_MASK: u64 = (1 << 33) - 1;
+/// The number of samples in an AAC frame.
+const AAC_FRAME_SAMPLES: u32 = 1024;
+const AAC_SAMPLE_RATES: [u32; 13] = [
+    96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+];
+/// H.264 profiles whose decoder configuration holds the chroma format and bit depths.
+const HIGH_PROFILES: [u8; 4] = [100, 110, 122, 144];
+/// How many packets are read between checks for cancellation.
+const CANCEL_CHECK_PACKETS: usize = 4096;
+
+/// A PES packet of an elementary stream.
+struct Pes {
+    pts: Option<u64>,
+    dts: Option<u64>,
+    data: Vec<u8>,
+}
+
+/// Reassembles the PES packets of the H.264 and AAC streams of a transport stream.
+#[derive(Default)]
+struct Demuxer {
+    pmt_pid: Option<u16>,
+    /// The stream type of each elementary stream.
+    streams: HashMap<u16, u8>,
+    unsupported: HashSet<u16>,
+    pes: HashMap<u16, Vec<u8>>,
+}
+
+impl Demuxer {
+    /// Read a packet, returning the stream type and PES packet it completed, if any.
+    fn push(&mut self, packet: &[u8]) -> Result<Option<(u8, Pes)>> {
+        if packet[0] != SYNC_BYTE {
+            return Err("Stream was not an MPEG transport stream, or was cut off.".into());
+        }
+        let unit_start = packet[1] & 0x40 != 0;
+        let pid = u16::from(packet[1] & 0x1F) << 8 | u16::from(packet[2]);
+        let adaptation = (packet[3] >> 4) & 3;
+        let start = match adaptation & 2 {
+            0 => 4,
+            _ => 5 + usize::from(packet[4]),
+        };
+        let payload = match packet.get(start..) {
+            Some(x) if adaptation & 1 != 0 && !x.is_empty() => x,
+            _ => return Ok(None),
+        };
+        if pid == 0 {
+            if unit_start {
+                self.pmt_pid = parse_pat(payload).or(self.pmt_pid);
+            }
+            return Ok(None);
+        }
+        if Some(pid) == self.pmt_pid {
+            if unit_start {
+                self.read_pmt(payload);
+            }
+            return Ok(None);
+        }
+        let stream_type = match self.streams.get(&pid) {
+            Some(x) => *x,
+            None => return Ok(None),
+        };
+        if !unit_start {
+            if let Some(pes) = self.pes.get_mut(&pid) {
+                pes.extend_from_slice(payload);
+            }
+            return Ok(None);
+        }
+        let done = self.pes.insert(pid, payload.to_vec());
+        Ok(done.and_then(|x| parse_pes(&x)).map(|x| (stream_type, x)))
+    }
+
+    /// Get the PES packets that were still being read when the stream ended.
+    fn finish(&mut self) -> Vec<(u8, Pes)> {
+        let mut pids = self.pes.keys().copied().collect::<Vec<_>>();
+        pids.sort_unstable();
+        pids.into_iter()
+            .filter_map(|pid| {
+                let pes = parse_pes(&self.pes.remove(&pid)?)?;
+                Some((self.streams[&pid], pes))
+            })
+            .collect()
+    }
+
+    fn read_pmt(&mut self, payload: &[u8]) {
+        let streams = match parse_pmt(payload) {
+            Some(x) => x,
+            None => return,
+        };
+        for (pid, stream_type) in streams {
+            if matches!(stream_type, STREAM_TYPE_H264 | STREAM_TYPE_AAC) {
+                self.streams.insert(pid, stream_type);
+            } else if self.unsupported.insert(pid) {
+                tracing::warn!(
+                    pid,
+                    stream_type,
+                    "leaving out stream that cannot be remuxed"
+                );
+            }
+        }
+    }
+}
+
+/// Get the section of a PSI table, which starts after its pointer field and ends before its CRC.
+fn section(payload: &[u8], table_id: u8) -> Option<&[u8]> {
+    let section = payload.get(1 + usize::from(*payload.first()?)..)?;
+    if *section.first()? != table_id {
+        return None;
+    }
+    let len = usize::from(section.get(1)? & 0x0F) << 8 | usize::from(*section.get(2)?);
+    section.get(..(3 + len).checked_sub(4)?)
+}
+
+/// Get the PID of the program map table of the first program.
+fn parse_pat(payload: &[u8]) -> Option<u16> {
+    section(payload, 0)?
+        .get(8..)?
+        .chunks_exact(4)
+        .find(|x| x[0] != 0 || x[1] != 0)
+        .map(|x| u16::from(x[2] & 0x1F) << 8 | u16::from(x[3]))
+}
+
+/// Get the PID and stream type of each elementary stream of a program map table.
+fn parse_pmt(payload: &[u8]) -> Option<Vec<(u16, u8)>> {
+    let section = section(payload, 2)?;
+    let info_len = usize::from(section.get(10)? & 0x0F) << 8 | usize::from(*section.get(11)?);
+    let mut rest = section.get(12 + info_len..)?;
+    let mut streams = Vec::new();
+    while rest.len() >= 5 {
+        let pid = u16::from(rest[1] & 0x1F) << 8 | u16::from(rest[2]);
+        streams.push((pid, rest[0]));
+        let info_len = usize::from(rest[3] & 0x0F) << 8 | usize::from(rest[4]);
+        rest = rest.get(5 + info_len..)?;
+    }
+    Some(streams)
+}
+
+fn parse_timestamp(data: &[u8]) -> Option<u64> {
+    let data = data.get(..5)?;
+    Some(
+        u64::from(data[0] >> 1 & 0x07) << 30
+            | u64::from(data[1]) << 22
+            | u64::from(data[2] >> 1) << 15
+            | u64::from(data[3]) << 7
+            | u64::from(data[4] >> 1),
+    )
+}
+
+fn parse_pes(data: &[u8]) -> Option<Pes> {
+    if data.get(..3)? != [0, 0, 1] {
+        return None;
+    }
+    let flags = data.get(7)? >> 6;
+    let payload = data.get(9 + usize::from(*data.get(8)?)..)?;
+    let pts = match flags & 2 {
+        0 => None,
+        _ => Some(parse_timestamp(data.get(9..)?)?),
+    };
+    let dts = match flags {
+        3 => Some(parse_timestamp(data.get(14..)?)?),
+        _ => pts,
+    };
+    Some(Pes {
+        pts,
+        dts,
+        data: payload.to_vec(),
+    })
+}
+
+/// Extend a 33 bit timestamp to the value closest to previous, so timestamps keep growing past the wrap around.
+fn unwrap_timestamp(timestamp: u64, previous: Option<u64>) -> u64 {
+    let previous = match previous {
+        Some(x) => x,
+        None => return timestamp,
+    };
+    let half = 1 << 32;
+    let candidate = (previous & !TIMESTAMP_MASK) | timestamp;
+    if candidate + half < previous {
+        candidate + TIMESTAMP_MASK + 1
+    } else if candidate > previous + half && candidate > TIMESTAMP_MASK {
+        candidate - TIMESTAMP_MASK - 1
+    } else {
+        candidate
+    }
+}
+
+/// Reads the bits of an H.264 parameter set, with emulation prevention bytes removed.
+struct BitReader {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl BitReader {
+    fn new(nal: &[u8]) -> Self {
+        let mut data = Vec::with_capacity(nal.len());
+        let mut zeros = 0;
+        for &byte in nal {
+            if zeros >= 2 && byte == 3 {
+                zeros = 0;
+                continue;
+            }
+            zeros = if byte == 0 { zeros + 1 } else { 0 };
+            data.push(byte);
+        }
+        BitReader { data, pos: 0 }
+    }
+
+    fn bit(&mut self) -> Option<u32> {
+        let byte = self.data.get(self.pos / 8)?;
+        let bit = byte >> (7 - self.pos % 8) & 1;
+        self.pos += 1;
+        Some(u32::from(bit))
+    }
+
+    fn bits(&mut self, count: u32) -> Option<u32> {
+        (0..count).try_fold(0, |value, _| Some(value << 1 | self.bit()?))
+    }
+
+    /// Read an unsigned Exp-Golomb code.
+    fn ue(&mut self) -> Option<u32> {
+        let mut zeros = 0;
+        while self.bit()? == 0 {
+            zeros += 1;
+            if zeros > 31 {
+                return None;
+            }
+        }
+        Some(((1u64 << zeros) - 1 + u64::from(self.bits(zeros)?)) as u32)
+    }
+
+    /// Read a signed Exp-Golomb code.
+    fn se(&mut self) -> Option<i32> {
+        let value = i64::from(self.ue()?);
+        Some(match value % 2 {
+            0 => -(value / 2),
+            _ => (value + 1) / 2,
+        } as i32)
+    }
+}
+
+/// What the decoder configuration and sample entry need from a sequence parameter set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct SequenceParameters {
+    profile: u8,
+    compatibility: u8,
+    level: u8,
+    chroma_format: u32,
+    bit_depth_luma: u32,
+    bit_depth_chroma: u32,
+    width: u32,
+    height: u32,
+}
+
+fn skip_scaling_list(reader: &mut BitReader, size: u32) -> Option<()> {
+    let (mut last, mut next) = (8i32, 8i32);
+    for _ in 0..size {
+        if next != 0 {
+            next = (last + reader.se()?).rem_euclid(256);
+        }
+        if next != 0 {
+            last = next;
+        }
+    }
+    Some(())
+}
+
+/// Parse a sequence parameter set NAL unit.
+fn parse_sps(nal: &[u8]) -> Option<SequenceParameters> {
+    let mut reader = BitReader::new(nal);
+    reader.bits(8)?; // The NAL unit header.
+    let profile = reader.bits(8)? as u8;
+    let compatibility = reader.bits(8)? as u8;
+    let level = reader.bits(8)? as u8;
+    reader.ue()?; // The id of the parameter set.
+    let (mut chroma_format, mut bit_depth_luma, mut bit_depth_chroma) = (1, 8, 8);
+    if matches!(
+        profile,
+        100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135
+    ) {
+        chroma_format = reader.ue()?;
+        if chroma_format == 3 {
+            reader.bit()?; // Whether the colour planes are separate.
+        }
+        bit_depth_luma = reader.ue()? + 8;
+        bit_depth_chroma = reader.ue()? + 8;
+        reader.bit()?; // The transform bypass flag.
+        if reader.bit()? == 1 {
+            let lists = if chroma_format == 3 { 12 } else { 8 };
+            for i in 0..lists {
+                if reader.bit()? == 1 {
+                    skip_scaling_list(&mut reader, if i < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+    reader.ue()?; // The number of bits of frame numbers.
+    match reader.ue()? {
+        0 => {
+            reader.ue()?;
+        }
+        1 => {
+            reader.bit()?;
+            reader.se()?;
+            reader.se()?;
+            for _ in 0..reader.ue()? {
+                reader.se()?;
+            }
+        }
+        _ => (),
+    }
+    reader.ue()?; // The number of reference frames.
+    reader.bit()?; // Whether gaps in frame numbers are allowed.
+    let width_mbs = reader.ue()? + 1;
+    let height_units = reader.ue()? + 1;
+    let frame_mbs_only = reader.bit()?;
+    if frame_mbs_only == 0 {
+        reader.bit()?; // Whether frames and fields adapt per macroblock.
+    }
+    reader.bit()?; // The direct 8x8 inference flag.
+    let (mut left, mut right, mut top, mut bottom) = (0, 0, 0, 0);
+    if reader.bit()? == 1 {
+        left = reader.ue()?;
+        right = reader.ue()?;
+        top = reader.ue()?;
+        bottom = reader.ue()?;
+    }
+    let field_factor = 2 - frame_mbs_only;
+    let (crop_x, crop_y) = match chroma_format {
+        1 => (2, 2 * field_factor),
+        2 => (2, field_factor),
+        _ => (1, field_factor),
+    };
+    Some(SequenceParameters {
+        profile,
+        compatibility,
+        level,
+        chroma_format,
+        bit_depth_luma,
+        bit_depth_chroma,
+        width: (width_mbs * 16).checked_sub(crop_x * (left + right))?,
+        height: (field_factor * height_units * 16).checked_sub(crop_y * (top + bottom))?,
+    })
+}
+
+/// Split an H.264 access unit in Annex B format into its NAL units.
+fn split_nal_units(data: &[u8]) -> Vec<&[u8]> {
+    let mut units = Vec::new();
+    let mut start = None;
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i..i + 3] == [0, 0, 1] {
+            if let Some(start) = start {
+                units.push(&data[start..i]);
+            }
+            i += 3;
+            start = Some(i);
+        } else {
+            i += 1;
+        }
+    }
+    if let Some(start) = start {
+        units.push(&data[start..]);
+    }
+    // Zeros before a start code belong to the start code rather than the unit.
+    units
+        .into_iter()
+        .map(|x| &x[..x.len() - x.iter().rev().take_while(|x| **x == 0).count()])
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+#[derive(Clone, Copy)]
+struct Sample {
+    /// Where the sample starts in the output.
+    offset: u64,
+    size: u32,
+    /// The decode time, in the timescale of the track.
+    time: u64,
+    /// How far the presentation time is after the decode time.
+    composition_offset: u32,
+    sync: bool,
+}
+
+#[derive(Default)]
+struct Track {
+    samples: Vec<Sample>,
+    /// The first timestamp of the track, in the transport stream clock.
+    start: Option<u64>,
+    last_timestamp: Option<u64>,
+}
+
+impl Track {
+    /// Get the duration of each sample, taking the last as long as the one before it.
+    fn durations(&self) -> Vec<u32> {
+        let mut durations = self
+            .samples
+            .windows(2)
+            .map(|x| u32::try_from(x[1].time.saturating_sub(x[0].time)).unwrap_or(u32::MAX))
+            .collect::<Vec<_>>();
+        durations.push(durations.last().copied().unwrap_or(0));
+        durations
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct AudioConfig {
+    object_type: u8,
+    frequency_index: u8,
+    channels: u8,
+}
+
+impl AudioConfig {
+    fn sample_rate(&self) -> u32 {
+        AAC_SAMPLE_RATES[usize::from(self.frequency_index)]
+    }
+}
+
+/// Writes the samples of the streams to the media data of an MP4 file, and the movie box describing them after it.
+struct Muxer<W> {
+    output: W,
+    position: u64,
+    video: Track,
+    sps: Option<Vec<u8>>,
+    pps: Option<Vec<u8>>,
+    audio: Track,
+    audio_config: Option<AudioConfig>,
+}
+
+impl<W: Write> Muxer<W> {
+    fn write_sample(&mut self, data: &[u8]) -> Result<u64> {
+        self.output.write_all(data)?;
+        let offset = self.position;
+        self.position += data.len() as u64;
+        Ok(offset)
+    }
+
+    fn push_video(&mut self, pes: Pes) -> Result<()> {
+        let (pts, dts) = match (pes.pts, pes.dts) {
+            (Some(pts), Some(dts)) => (pts, dts),
+            _ => return Ok(()),
+        };
+        let mut sample = Vec::with_capacity(pes.data.len() + 16);
+        let mut sync = false;
+        for unit in split_nal_units(&pes.data) {
+            match unit[0] & 0x1F {
+                // Parameter sets go in the decoder configuration, and delimiters are not needed in MP4.
+                7 => {
+                    if self.sps.is_none() {
+                        self.sps = Some(unit.to_vec());
+                    }
+                    continue;
+                }
+                8 => {
+                    if self.pps.is_none() {
+                        self.pps = Some(unit.to_vec());
+                    }
+                    continue;
+                }
+                9 => continue,
+                5 => sync = true,
+                _ => (),
+            }
+            sample.extend((unit.len() as u32).to_be_bytes());
+            sample.extend(unit);
+        }
+        if sample.is_empty() {
+            return Ok(());
+        }
+        let time = unwrap_timestamp(dts, self.video.last_timestamp);
+        self.video.last_timestamp = Some(time);
+        let start = *self.video.start.get_or_insert(time);
+        let composition_offset = (pts.wrapping_sub(dts) & TIMESTAMP_MASK) as u32;
+        let offset = self.write_sample(&sample)?;
+        self.video.samples.push(Sample {
+            offset,
+            size: sample.len() as u32,
+            time: time.saturating_sub(start),
+            composition_offset,
+            sync,
+        });
+        Ok(())
+    }
+
+    fn push_audio(&mut self, pes: Pes) -> Result<()> {
+        if let Some(pts) = pes.pts {
+            let time = unwrap_timestamp(pts, self.audio.last_timestamp);
+            self.audio.last_timestamp = Some(time);
+            self.audio.start.get_or_insert(time);
+        }
+        let mut rest = &pes.data[..];
+        while rest.len() >= 7 && rest[0] == 0xFF && rest[1] & 0xF0 == 0xF0 {
+            let header_len = if rest[1] & 1 == 1 { 7 } else { 9 };
+            let frame_len = usize::from(rest[3] & 3) << 11
+                | usize::from(rest[4]) << 3
+                | usize::from(rest[5] >> 5);
+            if frame_len <= header_len || frame_len > rest.len() {
+                break;
+            }
+            let config = AudioConfig {
+                object_type: (rest[2] >> 6) + 1,
+                frequency_index: rest[2] >> 2 & 0x0F,
+                channels: (rest[2] & 1) << 2 | rest[3] >> 6,
+            };
+            if usize::from(config.frequency_index) >= AAC_SAMPLE_RATES.len() {
+                return Err("AAC stream had an invalid sample rate.".into());
+            }
+            match self.audio_config {
+                None => self.audio_config = Some(config),
+                Some(x) if x != config => {
+                    return Err(
+                        "AAC stream changed its configuration, which cannot be remuxed.".into(),
+                    )
+                }
+                _ => (),
+            }
+            let offset = self.write_sample(&rest[header_len..frame_len])?;
+            let index = self.audio.samples.len() as u64;
+            self.audio.samples.push(Sample {
+                offset,
+                size: (frame_len - header_len) as u32,
+                time: index * u64::from(AAC_FRAME_SAMPLES),
+                composition_offset: 0,
+                sync: true,
+            });
+            rest = &rest[frame_len..];
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, stream_type: u8, pes: Pes) -> Result<()> {
+        match stream_type {
+            STREAM_TYPE_H264 => self.push_video(pes),
+            _ => self.push_audio(pes),
+        }
+    }
+}
+
+fn matrix() -> Vec<u8> {
+    [0x0001_0000u32, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000]
+        .iter()
+        .flat_map(|x| x.to_be_bytes())
+        .collect()
+}
+
+/// Convert a duration in timescale to the timescale of the movie.
+fn to_movie_time(duration: u64, timescale: u64) -> u64 {
+    (u128::from(duration) * u128::from(MOVIE_TIMESCALE) / u128::from(timescale)) as u64
+}
+
+/// Get runs of equal values, as count and value.
+fn runs(values: impl IntoIterator<Item = u32>) -> Vec<(u32, u32)> {
+    let mut runs: Vec<(u32, u32)> = Vec::new();
+    for value in values {
+        match runs.last_mut() {
+            Some((count, last)) if *last == value => *count += 1,
+            _ => runs.push((1, value)),
+        }
+    }
+    runs
+}
+
+fn runs_box(kind: &[u8; 4], runs: &[(u32, u32)]) -> Vec<u8> {
+    let mut body = (runs.len() as u32).to_be_bytes().to_vec();
+    for (count, value) in runs {
+        body.extend(count.to_be_bytes());
+        body.extend(value.to_be_bytes());
+    }
+    make_full_box(kind, 0, 0, &body)
+}
+
+/// Get the sample table of a track, with samples that follow each other in the output grouped into chunks.
+fn sample_table(track: &Track, sample_entry: Vec<u8>, large_offsets: bool) -> Vec<u8> {
+    let mut descriptions = 1u32.to_be_bytes().to_vec();
+    descriptions.extend(sample_entry);
+    let mut table = make_full_box(b"stsd", 0, 0, &descriptions);
+    table.extend(runs_box(b"stts", &runs(track.durations())));
+    if track.samples.iter().any(|x| x.composition_offset != 0) {
+        let offsets = runs(track.samples.iter().map(|x| x.composition_offset));
+        table.extend(runs_box(b"ctts", &offsets));
+    }
+    if track.samples.iter().any(|x| !x.sync) {
+        let sync = track
+            .samples
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| x.sync)
+            .map(|(i, _)| i as u32 + 1)
+            .collect::<Vec<_>>();
+        let mut body = (sync.len() as u32).to_be_bytes().to_vec();
+        body.extend(sync.iter().flat_map(|x| x.to_be_bytes()));
+        table.extend(make_full_box(b"stss", 0, 0, &body));
+    }
+
+    let mut chunks: Vec<(u64, u32)> = Vec::new();
+    let mut end = None;
+    for sample in &track.samples {
+        match chunks.last_mut() {
+            Some((_, count)) if end == Some(sample.offset) => *count += 1,
+            _ => chunks.push((sample.offset, 1)),
+        }
+        end = Some(sample.offset + u64::from(sample.size));
+    }
+    let mut sample_to_chunk: Vec<(u32, u32)> = Vec::new();
+    for (i, (_, count)) in chunks.iter().enumerate() {
+        if sample_to_chunk.last().map(|x| x.1) != Some(*count) {
+            sample_to_chunk.push((i as u32 + 1, *count));
+        }
+    }
+    let mut body = (sample_to_chunk.len() as u32).to_be_bytes().to_vec();
+    for (first_chunk, count) in sample_to_chunk {
+        body.extend(first_chunk.to_be_bytes());
+        body.extend(count.to_be_bytes());
+        body.extend(1u32.to_be_bytes());
+    }
+    table.extend(make_full_box(b"stsc", 0, 0, &body));
+
+    let mut body = 0u32.to_be_bytes().to_vec();
+    body.extend((track.samples.len() as u32).to_be_bytes());
+    body.extend(track.samples.iter().flat_map(|x| x.size.to_be_bytes()));
+    table.extend(make_full_box(b"stsz", 0, 0, &body));
+
+    let mut body = (chunks.len() as u32).to_be_bytes().to_vec();
+    match large_offsets {
+        true => body.extend(chunks.iter().flat_map(|x| x.0.to_be_bytes())),
+        false => body.extend(chunks.iter().flat_map(|x| (x.0 as u32).to_be_bytes())),
+    }
+    table.extend(make_full_box(
+        if large_offsets { b"co64" } else { b"stco" },
+        0,
+        0,
+        &body,
+    ));
+    make_box(b"stbl", &table)
+}
+
+/// Get the sample entry of an H.264 track, with the decoder configuration built from its parameter sets.
+fn avc_sample_entry(params: &SequenceParameters, sps: &[u8], pps: &[u8]) -> Vec<u8> {
+    let mut config = vec![1, params.profile, params.compatibility, params.level];
+    // Lengths of NAL units take 4 bytes, and there is one parameter set of each kind.
+    config.extend([0xFF, 0xE1]);
+    config.extend((sps.len() as u16).to_be_bytes());
+    config.extend(sps);
+    config.push(1);
+    config.extend((pps.len() as u16).to_be_bytes());
+    config.extend(pps);
+    if HIGH_PROFILES.contains(&params.profile) {
+        config.extend([
+            0xFC | params.chroma_format as u8,
+            0xF8 | (params.bit_depth_luma - 8) as u8,
+            0xF8 | (params.bit_depth_chroma - 8) as u8,
+            0,
+        ]);
+    }
+    let mut entry = vec![0; 6];
+    entry.extend(1u16.to_be_bytes()); // The data reference index.
+    entry.extend([0; 16]);
+    entry.extend((params.width as u16).to_be_bytes());
+    entry.extend((params.height as u16).to_be_bytes());
+    // 72 dpi horizontally and vertically.
+    entry.extend(0x0048_0000u32.to_be_bytes());
+    entry.extend(0x0048_0000u32.to_be_bytes());
+    entry.extend([0; 4]);
+    entry.extend(1u16.to_be_bytes()); // One frame per sample.
+    entry.extend([0; 32]); // The compressor name.
+    entry.extend(0x0018u16.to_be_bytes()); // The colour depth.
+    entry.extend((-1i16).to_be_bytes());
+    entry.extend(make_box(b"avcC", &config));
+    make_box(b"avc1", &entry)
+}
+
+/// Get an MPEG-4 descriptor of tag holding body.
+fn descriptor(tag: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag, body.len() as u8];
+    out.extend(body);
+    out
+}
+
+/// Get the sample entry of an AAC track.
+fn aac_sample_entry(config: &AudioConfig) -> Vec<u8> {
+    let specific = [
+        config.object_type << 3 | config.frequency_index >> 1,
+        (config.frequency_index & 1) << 7 | config.channels << 3,
+    ];
+    let mut decoder = vec![0x40, 0x15]; // MPEG-4 audio, as an audio stream.
+    decoder.extend([0; 11]); // The buffer size and bitrates, which are optional.
+    decoder.extend(descriptor(0x05, &specific));
+    let mut stream = vec![0, 0, 0]; // The stream id and flags.
+    stream.extend(descriptor(0x04, &decoder));
+    stream.extend(descriptor(0x06, &[0x02]));
+    let mut entry = vec![0; 6];
+    entry.extend(1u16.to_be_bytes()); // The data reference index.
+    entry.extend([0; 8]);
+    entry.extend(u16::from(config.channels.max(1)).to_be_bytes());
+    entry.extend(16u16.to_be_bytes()); // The sample size.
+    entry.extend([0; 4]);
+    // The sample rate as a 16.16 fixed point number, which rates above 65535 do not fit.
+    let rate = u16::try_from(config.sample_rate()).unwrap_or(0);
+    entry.extend((u32::from(rate) << 16).to_be_bytes());
+    entry.extend(make_full_box(b"esds", 0, 0, &descriptor(0x03, &stream)));
+    make_box(b"mp4a", &entry)
+}
+
+enum TrackKind {
+    Video { width: u32, height: u32 },
+    Audio,
+}
+
+struct TrackInfo<'a> {
+    id: u32,
+    kind: TrackKind,
+    timescale: u64,
+    track: &'a Track,
+    sample_entry: Vec<u8>,
+    /// Where presentation of the media starts, in the timescale of the track.
+    media_start: u64,
+}
+
+impl TrackInfo<'_> {
+    /// Get when the track starts being presented, in the transport stream clock.
+    fn presentation_start(&self) -> u64 {
+        let start = self.track.start.unwrap_or(0);
+        start + self.media_start * TS_CLOCK / self.timescale
+    }
+
+    fn media_duration(&self) -> u64 {
+        self.track.durations().iter().map(|x| u64::from(*x)).sum()
+    }
+
+    /// Get the track box, and the duration of the track in the timescale of the movie.
+    fn build(&self, movie_start: u64, large_offsets: bool) -> (Vec<u8>, u64) {
+        let media_duration = self.media_duration();
+        // An empty edit delays a track that starts after the other, so they stay in sync.
+        let delay = to_movie_time(self.presentation_start() - movie_start, TS_CLOCK);
+        let shown = to_movie_time(
+            media_duration.saturating_sub(self.media_start),
+            self.timescale,
+        );
+        let mut edits = Vec::new();
+        if delay > 0 {
+            edits.push((delay, -1i64));
+        }
+        edits.push((shown, self.media_start as i64));
+        let duration = delay + shown;
+
+        let mut edit_list = (edits.len() as u32).to_be_bytes().to_vec();
+        for (segment_duration, media_time) in &edits {
+            edit_list.extend(segment_duration.to_be_bytes());
+            edit_list.extend(media_time.to_be_bytes());
+            edit_list.extend(0x0001_0000u32.to_be_bytes()); // A rate of 1.
+        }
+        let edit = make_box(b"edts", &make_full_box(b"elst", 1, 0, &edit_list));
+
+        let (volume, width, height, handler, name, media_header) = match self.kind {
+            TrackKind::Video { width, height } => (
+                0u16,
+                width,
+                height,
+                b"vide",
+                &b"VideoHandler\0"[..],
+                make_full_box(b"vmhd", 0, 1, &[0; 8]),
+            ),
+            TrackKind::Audio => (
+                0x0100,
+                0,
+                0,
+                b"soun",
+                &b"SoundHandler\0"[..],
+                make_full_box(b"smhd", 0, 0, &[0; 4]),
+            ),
+        };
+        let mut header = vec![0; 16]; // The creation and modification times.
+        header.extend(self.id.to_be_bytes());
+        header.extend([0; 4]);
+        header.extend(duration.to_be_bytes());
+        header.extend([0; 12]); // Reserved, the layer and the alternate group.
+        header.extend(volume.to_be_bytes());
+        header.extend([0; 2]);
+        header.extend(matrix());
+        header.extend((width << 16).to_be_bytes());
+        header.extend((height << 16).to_be_bytes());
+        // The track is enabled and part of the presentation.
+        let track_header = make_full_box(b"tkhd", 1, 3, &header);
+
+        let mut header = vec![0; 16];
+        header.extend((self.timescale as u32).to_be_bytes());
+        header.extend(media_duration.to_be_bytes());
+        header.extend(0x55C4u16.to_be_bytes()); // The language, which is undetermined.
+        header.extend([0; 2]);
+        let mut handler_body = vec![0; 4];
+        handler_body.extend(handler);
+        handler_body.extend([0; 12]);
+        handler_body.extend(name);
+        let mut references = 1u32.to_be_bytes().to_vec();
+        // The media is in the same file.
+        references.extend(make_full_box(b"url ", 0, 1, &[]));
+        let mut info = media_header;
+        info.extend(make_box(
+            b"dinf",
+            &make_full_box(b"dref", 0, 0, &references),
+        ));
+        info.extend(sample_table(
+            self.track,
+            self.sample_entry.clone(),
+            large_offsets,
+        ));
+        let mut media = make_full_box(b"mdhd", 1, 0, &header);
+        media.extend(make_full_box(b"hdlr", 0, 0, &handler_body));
+        media.extend(make_box(b"minf", &info));
+
+        let mut track = track_header;
+        track.extend(edit);
+        track.extend(make_box(b"mdia", &media));
+        (make_box(b"trak", &track), duration)
+    }
+}
+
+impl<W: Write> Muxer<W> {
+    /// Get the movie box describing the samples written, with tags in its user data.
+    fn movie_box(&self, tags: &[(String, String)]) -> Result<Vec<u8>> {
+        let mut tracks = Vec::new();
+        if !self.video.samples.is_empty() {
+            let (sps, pps) = self
+                .sps
+                .as_ref()
+                .zip(self.pps.as_ref())
+                .ok_or("H.264 stream had no parameter sets.")?;
+            let params = parse_sps(sps).ok_or("H.264 stream had an invalid parameter set.")?;
+            let media_start = self
+                .video
+                .samples
+                .iter()
+                .map(|x| x.time + u64::from(x.composition_offset))
+                .min()
+                .unwrap_or(0);
+            tracks.push(TrackInfo {
+                id: tracks.len() as u32 + 1,
+                kind: TrackKind::Video {
+                    width: params.width,
+                    height: params.height,
+                },
+                timescale: TS_CLOCK,
+                track: &self.video,
+                sample_entry: avc_sample_entry(&params, sps, pps),
+                media_start,
+            });
+        }
+        if let (false, Some(config)) = (self.audio.samples.is_empty(), &self.audio_config) {
+            tracks.push(TrackInfo {
+                id: tracks.len() as u32 + 1,
+                kind: TrackKind::Audio,
+                timescale: u64::from(config.sample_rate()),
+                track: &self.audio,
+                sample_entry: aac_sample_entry(config),
+                media_start: 0,
+            });
+        }
+        if tracks.is_empty() {
+            return Err("Stream held no H.264 video or AAC audio to remux.".into());
+        }
+        let movie_start = tracks
+            .iter()
+            .map(TrackInfo::presentation_start)
+            .min()
+            .unwrap_or(0);
+        let large_offsets = self.position > u64::from(u32::MAX);
+        let mut movie = Vec::new();
+        let mut duration = 0;
+        for track in &tracks {
+            let (track, track_duration) = track.build(movie_start, large_offsets);
+            movie.extend(track);
+            duration = duration.max(track_duration);
+        }
+
+        let mut header = vec![0; 16]; // The creation and modification times.
+        header.extend((MOVIE_TIMESCALE as u32).to_be_bytes());
+        header.extend(duration.to_be_bytes());
+        header.extend(0x0001_0000u32.to_be_bytes()); // A rate of 1.
+        header.extend(0x0100u16.to_be_bytes()); // Full volume.
+        header.extend([0; 10]);
+        header.extend(matrix());
+        header.extend([0; 24]);
+        header.extend((tracks.len() as u32 + 1).to_be_bytes()); // The next track id.
+        let mut out = make_full_box(b"mvhd", 1, 0, &header);
+        out.extend(movie);
+        out.extend(mp4::user_data_box(tags));
+        Ok(make_box(b"moov", &out))
+    }
+}
+
+/// Returns true if data starts like an MPEG transport stream.
+pub(crate) fn is_transport_stream(data: &[u8]) -> bool {
+    data.first() == Some(&SYNC_BYTE) && matches!(data.get(PACKET_LEN), None | Some(&SYNC_BYTE))
+}
+
+/// Remux the MPEG transport stream at input into an MP4 file at output with tags, without re-encoding.
+/// Only H.264 video and AAC audio are kept, as other streams cannot be stored in MP4 without FFMPEG.
+/// Returns Cancelled if handle is cancelled while remuxing.
+fn remux_transport_stream(
+    input: &Path,
+    output: &Path,
+    tags: &[(String, String)],
+    handle: &JobHandle,
+) -> Result<()> {
+    let mut input = BufReader::new(File::open(input)?);
+    let mut file = BufWriter::new(File::create(output)?);
+    let mut header = make_box(b"ftyp", b"isom\0\0\x02\0isomiso2avc1mp41");
+    // The size of the media data is filled in once known, as a 64 bit size so any length fits.
+    header.extend(1u32.to_be_bytes());
+    header.extend(b"mdat");
+    header.extend(0u64.to_be_bytes());
+    file.write_all(&header)?;
+    let data_start = header.len() as u64;
+    let mut muxer = Muxer {
+        output: file,
+        position: data_start,
+        video: Track::default(),
+        sps: None,
+        pps: None,
+        audio: Track::default(),
+        audio_config: None,
+    };
+    let mut demuxer = Demuxer::default();
+    let mut packet = [0; PACKET_LEN];
+    for count in 0usize.. {
+        if count.is_multiple_of(CANCEL_CHECK_PACKETS) && handle.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+        match input.read_exact(&mut packet) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            x => x?,
+        }
+        if let Some((stream_type, pes)) = demuxer.push(&packet)? {
+            muxer.push(stream_type, pes)?;
+        }
+    }
+    for (stream_type, pes) in demuxer.finish() {
+        muxer.push(stream_type, pes)?;
+    }
+    let movie = muxer.movie_box(tags)?;
+    let data_end = muxer.position;
+    let mut file = muxer.output;
+    file.write_all(&movie)?;
+    // 16 bytes before the media data is where the box of the media data starts.
+    file.seek(SeekFrom::Start(data_start - 8))?;
+    file.write_all(&(data_end - data_start + 16).to_be_bytes())?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Write the stream at input to an MP4 file at output with tags, remuxing a transport stream
+/// or copying a fragmented MP4 stream with the tags written into it.
+pub(crate) fn remux(
+    input: &Path,
+    output: &Path,
+    tags: &[(String, String)],
+    handle: &JobHandle,
+) -> Result<()> {
+    let mut start = Vec::new();
+    File::open(input)?
+        .take(PACKET_LEN as u64 + 1)
+        .read_to_end(&mut start)?;
+    if is_transport_stream(&start) {
+        remux_transport_stream(input, output, tags, handle)
+    } else if mp4::is_mp4(&start) {
+        mp4::write_tags(input, output, tags)
+    } else {
+        Err("Stream was neither an MPEG transport stream nor MP4, so it cannot be saved as MP4 without a Converter.".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIDEO_PID: u16 = 0x100;
+    const AUDIO_PID: u16 = 0x101;
+
+    #[derive(Default)]
+    struct BitWriter {
+        data: Vec<u8>,
+        len: usize,
+    }
+
+    impl BitWriter {
+        fn bits(&mut self, value: u32, count: u32) -> &mut Self {
+            for i in (0..count).rev() {
+                if self.len.is_multiple_of(8) {
+                    self.data.push(0);
+                }
+                *self.data.last_mut().unwrap() |= ((value >> i & 1) as u8) << (7 - self.len % 8);
+                self.len += 1;
+            }
+            self
+        }
+
+        fn ue(&mut self, value: u32) -> &mut Self {
+            let count = 32 - (value + 1).leading_zeros();
+            self.bits(0, count - 1).bits(value + 1, count)
+        }
+
+        /// End with the stop bit of the RBSP trailing bits.
+        fn finish(&mut self) -> Vec<u8> {
+            self.bits(1, 1);
+            self.data.clone()
+        }
+    }
+
+    /// Get a baseline SPS of a 1920x1080 picture, which is coded as 1920x1088 and cropped.
+    fn sps() -> Vec<u8> {
+        let mut writer = BitWriter::default();
+        writer.bits(0x67, 8).bits(66, 8).bits(0xC0, 8).bits(40, 8);
+        writer.ue(0).ue(0).ue(2).ue(1).bits(0, 1);
+        writer.ue(119).ue(67).bits(1, 1).bits(1, 1);
+        writer.bits(1, 1).ue(0).ue(0).ue(0).ue(4).bits(0, 1);
+        writer.finish()
+    }
+
+    #[test]
+    fn parse_sps_reads_the_cropped_size() {
+        let params = parse_sps(&sps()).unwrap();
+        assert_eq!((params.width, params.height), (1920, 1080));
+        assert_eq!((params.profile, params.level), (66, 40));
+
+        let mut writer = BitWriter::default();
+        writer
+            .bits(0x67, 8)
+            .bits(100, 8)
+            .bits(0, 8)
+            .bits(31, 8)
+            .ue(0);
+        // 4:2:0 at 10 bits, with a scaling list.
+        writer.ue(1).ue(2).ue(2).bits(0, 1).bits(1, 1);
+        writer.bits(1, 1);
+        for _ in 0..16 {
+            writer.ue(0);
+        }
+        writer.bits(0, 7);
+        writer.ue(0).ue(0).ue(4).ue(1).bits(0, 1);
+        writer
+            .ue(79)
+            .ue(44)
+            .bits(1, 1)
+            .bits(1, 1)
+            .bits(0, 1)
+            .bits(0, 1);
+        let params = parse_sps(&writer.finish()).unwrap();
+        assert_eq!((params.width, params.height), (1280, 720));
+        assert_eq!((params.bit_depth_luma, params.chroma_format), (10, 1));
+    }
+
+    #[test]
+    fn bit_reader_removes_emulation_prevention() {
+        let mut reader = BitReader::new(&[0, 0, 3, 1, 0, 0, 3]);
+        assert_eq!(reader.bits(24), Some(1));
+        assert_eq!(reader.bits(16), Some(0));
+        assert_eq!(reader.bit(), None);
+    }
+
+    #[test]
+    fn split_nal_units_handles_both_start_codes() {
+        let data = [
+            0, 0, 0, 1, 9, 0xF0, 0, 0, 1, 0x65, 1, 2, 0, 0, 0, 1, 0x41, 3,
+        ];
+        let units = split_nal_units(&data);
+        assert_eq!(units, [&[9, 0xF0][..], &[0x65, 1, 2], &[0x41, 3]]);
+    }
+
+    #[test]
+    fn unwrap_timestamp_continues_past_the_wrap_around() {
+        assert_eq!(unwrap_timestamp(100, None), 100);
+        assert_eq!(
+            unwrap_timestamp(100, Some(TIMESTAMP_MASK - 50)),
+            TIMESTAMP_MASK + 101
+        );
+        assert_eq!(
+            unwrap_timestamp(TIMESTAMP_MASK - 50, Some(TIMESTAMP_MASK + 101)),
+            TIMESTAMP_MASK - 50
+        );
+        assert_eq!(unwrap_timestamp(2000, Some(1000)), 2000);
+    }
+
+    /// Split payload into transport stream packets of pid.
+    fn packets(pid: u16, payload: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (i, chunk) in payload.chunks(184).enumerate() {
+            let unit_start = if i == 0 { 0x40 } else { 0 };
+            out.extend([SYNC_BYTE, unit_start | (pid >> 8) as u8, pid as u8]);
+            if chunk.len() == 184 {
+                out.push(0x10);
+            } else {
+                // An adaptation field of stuffing fills the rest of the packet.
+                let stuffing = 183 - chunk.len();
+                out.extend([0x30, stuffing as u8]);
+                if stuffing > 0 {
+                    out.push(0);
+                    out.extend(vec![0xFF; stuffing - 1]);
+                }
+            }
+            out.extend(chunk);
+        }
+        out
+    }
+
+    fn timestamp(prefix: u8, ts: u64) -> [u8; 5] {
+        [
+            prefix << 4 | (ts >> 29 & 0x0E) as u8 | 1,
+            (ts >> 22) as u8,
+            (ts >> 14 & 0xFE) as u8 | 1,
+            (ts >> 7) as u8,
+            (ts << 1 & 0xFE) as u8 | 1,
+        ]
+    }
+
+    fn video_pes(pts: u64, dts: u64, units: &[&[u8]]) -> Vec<u8> {
+        let mut pes = vec![0, 0, 1, 0xE0, 0, 0, 0x80, 0xC0, 10];
+        pes.extend(timestamp(3, pts));
+        pes.extend(timestamp(1, dts));
+        for unit in units {
+            pes.extend([0, 0, 0, 1]);
+            pes.extend(*unit);
+        }
+        pes
+    }
+
+    fn adts_frame(payload: &[u8]) -> Vec<u8> {
+        let len = payload.len() + 7;
+        // AAC LC at 48000 Hz in stereo.
+        let mut frame = vec![
+            0xFF,
+            0xF1,
+            1 << 6 | 3 << 2,
+            2 << 6 | (len >> 11) as u8,
+            (len >> 3) as u8,
+            (len as u8 & 7) << 5 | 0x1F,
+            0xFC,
+        ];
+        frame.extend(payload);
+        frame
+    }
+
+    fn transport_stream() -> Vec<u8> {
+        let mut pat = vec![0, 0, 0xB0, 13, 0, 1, 0xC1, 0, 0, 0, 1, 0xF0, 0];
+        pat.extend([0; 4]);
+        let mut pmt = vec![0, 2, 0xB0, 23, 0, 1, 0xC1, 0, 0, 0xE1, 0, 0xF0, 0];
+        pmt.extend([STREAM_TYPE_H264, 0xE1, 0, 0xF0, 0]);
+        pmt.extend([STREAM_TYPE_AAC, 0xE1, 1, 0xF0, 0]);
+        // An ID3 stream, which is left out.
+        pmt.extend([0x15, 0xE1, 2, 0xF0, 0]);
+        pmt.extend([0; 4]);
+        let sps = sps();
+        let mut audio = vec![0, 0, 1, 0xC0, 0, 0, 0x80, 0x80, 5];
+        audio.extend(timestamp(2, 97_500));
+        audio.extend(adts_frame(&[1; 10]));
+        audio.extend(adts_frame(&[2; 12]));
+
+        let mut ts = packets(0, &pat);
+        ts.extend(packets(0x1000, &pmt));
+        let idr = vec![0x65; 300];
+        ts.extend(packets(
+            VIDEO_PID,
+            &video_pes(93_000, 90_000, &[&[9, 0xF0], &sps, &[0x68, 0xCE], &idr]),
+        ));
+        ts.extend(packets(AUDIO_PID, &audio));
+        ts.extend(packets(
+            VIDEO_PID,
+            &video_pes(99_000, 93_000, &[&[0x41, 1]]),
+        ));
+        ts.extend(packets(
+            VIDEO_PID,
+            &video_pes(96_000, 96_000, &[&[0x01, 2]]),
+        ));
+        ts
+    }
+
+    fn find<'a>(data: &'a [u8], kind: &[u8; 4]) -> &'a [u8] {
+        let at = data.windows(4).position(|x| x == kind).unwrap();
+        let size = u32::from_be_bytes(data[at - 4..at].try_into().unwrap()) as usize;
+        &data[at + 4..at - 4 + size]
+    }
+
+    fn find_all<'a>(data: &'a [u8], kind: &[u8; 4]) -> Vec<&'a [u8]> {
+        let mut found = Vec::new();
+        let mut rest = data;
+        while let Some(at) = rest.windows(4).position(|x| x == kind) {
+            found.push(find(rest, kind));
+            rest = &rest[at + 4..];
+        }
+        found
+    }
+
+    #[test]
+    fn remux_writes_tracks_and_tags() {
+        let path =
+            std::env::temp_dir().join(format!("dr-downloader-remux-{}.ts", std::process::id()));
+        let out_path = path.with_extension("mp4");
+        std::fs::write(&path, transport_stream()).unwrap();
+        let tags = [("title".to_owned(), "Episode".to_owned())];
+        remux(&path, &out_path, &tags, &JobHandle::new()).unwrap();
+        let mp4 = std::fs::read(&out_path).unwrap();
+        std::fs::remove_file(&path).ok();
+        std::fs::remove_file(&out_path).ok();
+
+        assert_eq!(&mp4[4..8], b"ftyp");
+        let data_start = u32::from_be_bytes(mp4[..4].try_into().unwrap()) as usize;
+        assert_eq!(&mp4[data_start + 4..data_start + 8], b"mdat");
+        let data_len = u64::from_be_bytes(mp4[data_start + 8..data_start + 16].try_into().unwrap());
+        let movie = &mp4[data_start + data_len as usize..];
+        assert_eq!(&movie[4..8], b"moov");
+        // The first sample is the IDR picture alone, with its length in front.
+        let first = &mp4[data_start + 16..];
+        assert_eq!(first[..4], 300u32.to_be_bytes());
+        assert_eq!(first[4..304], [0x65; 300]);
+
+        let tracks = find_all(movie, b"trak");
+        assert_eq!(tracks.len(), 2);
+        let (video, audio) = (tracks[0], tracks[1]);
+        let header = find(video, b"tkhd");
+        assert_eq!(header[header.len() - 8..], [7, 0x80, 0, 0, 4, 0x38, 0, 0]);
+        assert_eq!(find(video, b"avcC")[8..8 + sps().len()], sps()[..]);
+        // Samples are numbered from 1, and only the first is a sync sample.
+        assert_eq!(find(video, b"stss")[4..], [0, 0, 0, 1, 0, 0, 0, 1]);
+        assert_eq!(find(video, b"stsz")[8..12], 3u32.to_be_bytes());
+        let offsets = find(video, b"ctts");
+        assert_eq!(offsets[4..8], 3u32.to_be_bytes());
+        assert_eq!(offsets[8..16], [0, 0, 0, 1, 0, 0, 0x0B, 0xB8]);
+        // Presentation starts at the first picture shown rather than the first decoded.
+        let edits = find(video, b"elst");
+        assert_eq!(edits[4..8], 1u32.to_be_bytes());
+        assert_eq!(edits[16..24], 3000i64.to_be_bytes());
+
+        assert_eq!(find(audio, b"stsz")[8..12], 2u32.to_be_bytes());
+        assert_eq!(find(audio, b"mdhd")[20..24], 48000u32.to_be_bytes());
+        let esds = find(audio, b"esds");
+        assert!(esds.windows(4).any(|x| x == [0x05, 2, 0x11, 0x90]));
+        // The audio starts 50 ms after the video.
+        let edits = find(audio, b"elst");
+        assert_eq!(edits[4..8], 2u32.to_be_bytes());
+        assert_eq!(edits[8..16], 50u64.to_be_bytes());
+        assert_eq!(edits[16..24], (-1i64).to_be_bytes());
+
+        assert_eq!(&find(find(movie, b"\xa9nam"), b"data")[8..], b"Episode");
+    }
+
+    #[test]
+    fn remux_rejects_other_streams() {
+        let path = std::env::temp_dir().join(format!(
+            "dr-downloader-remux-other-{}.ts",
+            std::process::id()
+        ));
+        let out_path = path.with_extension("mp4");
+        std::fs::write(&path, b"<html>not a stream</html>").unwrap();
+        assert!(remux(&path, &out_path, &[], &JobHandle::new()).is_err());
+        std::fs::remove_file(&path).ok();
+        std::fs::remove_file(&out_path).ok();
+        assert!(is_transport_stream(&transport_stream()));
+        assert!(!is_transport_stream(b"\0\0\0\x18ftypisom"));
+    }
+}
@@ -0,0 +1,321 @@
+use crate::error::{is_cancelled, OkOrGeneric, Result};
+use crate::format::Format;
+use crate::models::episode::{EpisodeInfo, EpisodeMetadata};
+use serde_json::{json, Value};
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How a single episode of a job went.
+#[derive(Clone, Debug)]
+pub enum EpisodeOutcome {
+    /// Path is None when the episode was only downloaded to memory.
+    Saved {
+        path: Option<PathBuf>,
+        bytes: u64,
+        /// How long the episode took to save.
+        elapsed: Duration,
+    },
+    Skipped {
+        reason: String,
+    },
+    Cancelled,
+    Failed {
+        error: Arc<dyn Error + Send + Sync>,
+    },
+}
+
+impl EpisodeOutcome {
+    /// Get the outcome of an episode that ended in err.
+    pub(crate) fn from_error(err: Box<dyn Error + Send + Sync>) -> Self {
+        match is_cancelled(err.as_ref()) {
+            true => EpisodeOutcome::Cancelled,
+            false => EpisodeOutcome::Failed { error: err.into() },
+        }
+    }
+
+    pub(crate) fn from_result(result: Result<EpisodeOutcome>) -> Self {
+        result.unwrap_or_else(Self::from_error)
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            EpisodeOutcome::Saved {
+                path,
+                bytes,
+                elapsed,
+            } => json!({
+                "status": "saved",
+                "path": path.as_ref().map(|x| x.to_string_lossy()),
+                "bytes": bytes,
+                "elapsed_secs": elapsed.as_secs_f64(),
+            }),
+            EpisodeOutcome::Skipped { reason } => json!({ "status": "skipped", "reason": reason }),
+            EpisodeOutcome::Cancelled => json!({ "status": "cancelled" }),
+            EpisodeOutcome::Failed { error } => {
+                json!({ "status": "failed", "error": error.to_string() })
+            }
+        }
+    }
+
+    fn parse(json: &Value) -> Result<Self> {
+        let status = json["status"]
+            .as_str()
+            .ok_or_generic("Could not get the status of an episode outcome.")?;
+        Ok(match status {
+            "saved" => EpisodeOutcome::Saved {
+                path: json["path"].as_str().map(PathBuf::from),
+                bytes: json["bytes"].as_u64().unwrap_or(0),
+                elapsed: Duration::try_from_secs_f64(json["elapsed_secs"].as_f64().unwrap_or(0.0))
+                    .ok()
+                    .ok_or_generic("Episode outcome had an invalid elapsed_secs.")?,
+            },
+            "skipped" => EpisodeOutcome::Skipped {
+                reason: json["reason"].as_str().unwrap_or_default().to_owned(),
+            },
+            "cancelled" => EpisodeOutcome::Cancelled,
+            "failed" => {
+                let error: Box<dyn Error + Send + Sync> =
+                    json["error"].as_str().unwrap_or_default().into();
+                EpisodeOutcome::Failed {
+                    error: error.into(),
+                }
+            }
+            x => return Err(format!("Unknown episode outcome '{}'.", x).into()),
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EpisodeReport {
+    /// The url the episode was saved from.
+    pub url: String,
+    /// None if the episode could not be looked up.
+    pub episode: Option<EpisodeInfo>,
+    /// The directory the episode was saved to, which a retry saves it to again.
+    /// None if it was only downloaded to memory, or its batch item could not be resolved.
+    pub out_dir: Option<String>,
+    /// The format the episode was saved in, which a retry saves it in again. None like out_dir.
+    pub format: Option<Format>,
+    pub outcome: EpisodeOutcome,
+}
+
+/// The outcome of each episode of a job, in the order they were saved.
+#[derive(Clone, Debug, Default)]
+pub struct SaveReport {
+    pub episodes: Vec<EpisodeReport>,
+}
+
+impl SaveReport {
+    /// Add the episodes of other to this report.
+    pub fn extend(&mut self, other: SaveReport) {
+        self.episodes.extend(other.episodes);
+    }
+
+    pub fn saved(&self) -> impl Iterator<Item = &EpisodeReport> {
+        self.episodes
+            .iter()
+            .filter(|x| matches!(x.outcome, EpisodeOutcome::Saved { .. }))
+    }
+
+    pub fn skipped(&self) -> impl Iterator<Item = &EpisodeReport> {
+        self.episodes
+            .iter()
+            .filter(|x| matches!(x.outcome, EpisodeOutcome::Skipped { .. }))
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &EpisodeReport> {
+        self.episodes
+            .iter()
+            .filter(|x| matches!(x.outcome, EpisodeOutcome::Failed { .. }))
+    }
+
+    /// Get the episodes that failed or were cancelled, which a retry would save again.
+    pub fn unfinished(&self) -> impl Iterator<Item = &EpisodeReport> {
+        self.episodes.iter().filter(|x| {
+            matches!(
+                x.outcome,
+                EpisodeOutcome::Failed { .. } | EpisodeOutcome::Cancelled
+            )
+        })
+    }
+
+    /// Returns true if the job was cancelled before every episode was saved.
+    pub fn is_cancelled(&self) -> bool {
+        self.episodes
+            .iter()
+            .any(|x| matches!(x.outcome, EpisodeOutcome::Cancelled))
+    }
+
+    /// Returns true if no episode failed.
+    pub fn is_success(&self) -> bool {
+        self.failed().next().is_none()
+    }
+
+    /// Get the error of the first episode that failed.
+    pub fn first_error(&self) -> Option<&Arc<dyn Error + Send + Sync>> {
+        self.episodes.iter().find_map(|x| match &x.outcome {
+            EpisodeOutcome::Failed { error } => Some(error),
+            _ => None,
+        })
+    }
+
+    /// Get the report as JSON. Episodes only keep their id, name and title, and errors their message.
+    pub(crate) fn to_json(&self) -> Value {
+        let episodes = self
+            .episodes
+            .iter()
+            .map(|x| {
+                let episode = x.episode.as_ref().map(|x| {
+                    json!({
+                        "id": x.id,
+                        "name": x.name,
+                        "title": x.title(),
+                    })
+                });
+                json!({
+                    "url": x.url,
+                    "episode": episode,
+                    "out_dir": x.out_dir,
+                    "format": x.format.as_ref().map(Format::to_json),
+                    "outcome": x.outcome.to_json(),
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({ "episodes": episodes })
+    }
+
+    pub(crate) fn parse(json: &Value) -> Result<Self> {
+        let episodes = json["episodes"]
+            .as_array()
+            .ok_or_generic("Could not get the episodes of a report.")?
+            .iter()
+            .map(|x| {
+                let episode = match &x["episode"] {
+                    Value::Null => None,
+                    ep => Some(EpisodeInfo {
+                        name: ep["name"].as_str().unwrap_or_default().to_owned(),
+                        id: ep["id"]
+                            .as_str()
+                            .ok_or_generic("Could not get the id of a reported episode.")?
+                            .to_owned(),
+                        metadata: EpisodeMetadata {
+                            title: ep["title"].as_str().map(str::to_owned),
+                            ..Default::default()
+                        },
+                    }),
+                };
+                let format = match &x["format"] {
+                    Value::Null => None,
+                    format => Some(Format::parse(format)?),
+                };
+                Ok(EpisodeReport {
+                    url: x["url"]
+                        .as_str()
+                        .ok_or_generic("Could not get the url of a reported episode.")?
+                        .to_owned(),
+                    episode,
+                    out_dir: x["out_dir"].as_str().map(str::to_owned),
+                    format,
+                    outcome: EpisodeOutcome::parse(&x["outcome"])?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(SaveReport { episodes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Cancelled;
+
+    fn report(url: &str, outcome: EpisodeOutcome) -> EpisodeReport {
+        EpisodeReport {
+            url: url.to_owned(),
+            episode: Some(EpisodeInfo {
+                name: "tv-avisen".to_owned(),
+                id: "67890".to_owned(),
+                metadata: EpisodeMetadata {
+                    title: Some("TV Avisen".to_owned()),
+                    ..Default::default()
+                },
+            }),
+            out_dir: Some("out".to_owned()),
+            format: Some(Format::Mp4),
+            outcome,
+        }
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let mut lookup_failed = report("b", EpisodeOutcome::from_error("Unrecognized URL.".into()));
+        lookup_failed.episode = None;
+        lookup_failed.out_dir = None;
+        lookup_failed.format = None;
+        let mut custom = report("e", EpisodeOutcome::from_error("Timed out.".into()));
+        custom.out_dir = Some("out/news".to_owned());
+        custom.format = Some(Format::custom(".flv", "flv"));
+        let original = SaveReport {
+            episodes: vec![
+                report(
+                    "a",
+                    EpisodeOutcome::Saved {
+                        path: Some(PathBuf::from("out/tv-avisen.mp4")),
+                        bytes: 1024,
+                        elapsed: Duration::from_millis(1500),
+                    },
+                ),
+                lookup_failed,
+                report(
+                    "c",
+                    EpisodeOutcome::Skipped {
+                        reason: "File already exists.".to_owned(),
+                    },
+                ),
+                report("d", EpisodeOutcome::from_error(Cancelled.into())),
+                custom,
+            ],
+        };
+        let parsed = SaveReport::parse(&original.to_json()).unwrap();
+        assert_eq!(parsed.to_json(), original.to_json());
+        let ep = parsed.episodes[0].episode.as_ref().unwrap();
+        assert_eq!((ep.id.as_str(), ep.title()), ("67890", "TV Avisen"));
+        assert!(matches!(
+            parsed.episodes[0].outcome,
+            EpisodeOutcome::Saved { elapsed, .. } if elapsed == Duration::from_millis(1500)
+        ));
+        assert!(parsed.episodes[1].episode.is_none());
+        assert_eq!(parsed.episodes[0].out_dir.as_deref(), Some("out"));
+        assert_eq!(parsed.episodes[0].format, Some(Format::Mp4));
+        assert_eq!(parsed.episodes[1].format, None);
+        assert_eq!(parsed.episodes[4].out_dir.as_deref(), Some("out/news"));
+        assert_eq!(
+            parsed.episodes[4].format,
+            Some(Format::custom(".flv", "flv"))
+        );
+        assert_eq!(
+            parsed.first_error().unwrap().to_string(),
+            "Unrecognized URL."
+        );
+        assert_eq!(parsed.skipped().count(), 1);
+        assert!(parsed.is_cancelled());
+        assert_eq!(parsed.unfinished().count(), 3);
+    }
+
+    #[test]
+    fn parse_rejects_invalid_outcomes() {
+        let parse = |outcome: Value| {
+            SaveReport::parse(
+                &json!({ "episodes": [{ "url": "a", "episode": null, "outcome": outcome }] }),
+            )
+        };
+        assert!(parse(json!({ "status": "saved", "elapsed_secs": 2.5 })).is_ok());
+        assert!(parse(json!({ "status": "saved", "elapsed_secs": -1.0 })).is_err());
+        assert!(parse(json!({ "status": "saved", "elapsed_secs": 1e300 })).is_err());
+        assert!(parse(json!({ "status": "exploded" })).is_err());
+        assert!(parse(json!({})).is_err());
+        assert!(SaveReport::parse(&json!({})).is_err());
+    }
+}
@@ -1,10 +1,14 @@
 use crate::cacher::{get_or_set_token, get_token, set_token};
 use crate::error::{OkOrGeneric, Result};
-use crate::models::episode::EpisodeInfo;
+use crate::http;
+use crate::models::episode::{EpisodeInfo, EpisodeMetadata, EpisodeStream};
+use crate::models::SubtitleTrack;
 use crate::util::{find_char, rfind_char};
 use reqwest::{header, Client, StatusCode};
 use serde_json::Value;
 
+const MAX_AUTH_RETRIES: u32 = 3;
+
 #[derive(Clone)]
 pub struct Requester {
     net: Client,
@@ -16,6 +20,10 @@ impl Requester {
         Ok(Requester { net })
     }
 
+    pub(crate) fn get_client(&self) -> &Client {
+        &self.net
+    }
+
     async fn get_auth_token<'b>(net: &Client) -> Result<String> {
         const AUTH_ENDPOINT: &str = "https://production.dr-massive.com/api/authorization/anonymous-sso?device=web_browser&ff=idp%2Cldp%2Crpt&lang=da";
         let mut headers = header::HeaderMap::new();
@@ -23,12 +31,11 @@ impl Requester {
             "Content-Type",
             header::HeaderValue::from_static("application/json"),
         );
-        let response = net
+        let request = net
 			.post(AUTH_ENDPOINT)
 			.headers(headers)
-			.body("{\"deviceId\":\"632bdbff-d073-4b6c-85cb-76a0de00506d\",\"scopes\":[\"Catalog\"],\"optout\":true,\"cookieType\":\"Session\"}")
-			.send()
-			.await?;
+			.body("{\"deviceId\":\"632bdbff-d073-4b6c-85cb-76a0de00506d\",\"scopes\":[\"Catalog\"],\"optout\":true,\"cookieType\":\"Session\"}");
+        let response = http::send(net, request).await?;
 
         let status = response.status();
         if status != StatusCode::OK {
@@ -51,13 +58,12 @@ impl Requester {
             "https://production.dr-massive.com/api/authorization/refresh?ff=idp%2Cldp%2Crpt&lang=da";
         let mut headers = header::HeaderMap::new();
         headers.append("Content-Type", "application/json".parse()?);
-        let response = self
+        let request = self
             .net
             .post(REFRESH_ENDPOINT)
             .headers(headers)
-            .body(format!("{{ \"token\": \"{}\"}}", token))
-            .send()
-            .await?;
+            .body(format!("{{ \"token\": \"{}\"}}", token));
+        let response = http::send(&self.net, request).await?;
 
         let status = response.status();
         if status != StatusCode::OK {
@@ -128,13 +134,92 @@ impl Requester {
         Ok(EpisodeInfo {
             name: name.to_owned(),
             id: id.to_owned(),
+            metadata: EpisodeMetadata::default(),
         })
     }
 
+    fn construct_item_query_url(ep_id: &str) -> String {
+        format!("https://production.dr-massive.com/api/items/{}?device=web_browser&ff=idp%2Cldp%2Crpt&geoLocation=dk&isDeviceAbroad=false&lang=da&segments=drtv%2Coptedin&sub=Anonymous", ep_id)
+    }
+
+    fn parse_episode_metadata(item: &Value) -> EpisodeMetadata {
+        let string = |x: &Value| {
+            x.as_str()
+                .map(str::trim)
+                .filter(|x| !x.is_empty())
+                .map(str::to_owned)
+        };
+        let number = |x: &Value| x.as_u64().and_then(|x| u32::try_from(x).ok());
+        let season = &item["season"];
+        let show = &season["show"];
+        let image =
+            |x: &Value, kinds: &[&str]| kinds.iter().find_map(|kind| string(&x["images"][kind]));
+        // Dates are full timestamps, only the day is kept.
+        let air_date = [
+            &item["customFields"]["BroadcastTimeDK"],
+            &item["releaseDate"],
+        ]
+        .into_iter()
+        .chain(
+            item["offers"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|x| &x["startDate"]),
+        )
+        .find_map(|x| x.as_str()?.get(..10).map(str::to_owned))
+        .or_else(|| item["releaseYear"].as_u64().map(|x| x.to_string()));
+        EpisodeMetadata {
+            title: string(&item["episodeName"]).or_else(|| string(&item["title"])),
+            show: string(&show["title"]).or_else(|| string(&item["showTitle"])),
+            season: number(&season["seasonNumber"]).or_else(|| number(&item["seasonNumber"])),
+            episode: number(&item["episodeNumber"]),
+            description: string(&item["description"]).or_else(|| string(&item["shortDescription"])),
+            air_date,
+            genres: item["genres"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(string)
+                .collect(),
+            poster_url: image(item, &["poster", "tile", "wallpaper"]),
+            show_id: string(&show["id"])
+                .or_else(|| show["id"].as_u64().map(|x| x.to_string()))
+                .or_else(|| string(&item["showId"])),
+            show_description: string(&show["description"])
+                .or_else(|| string(&show["shortDescription"])),
+            show_poster_url: image(show, &["poster", "tile"]),
+            fanart_url: image(show, &["wallpaper"]).or_else(|| image(item, &["wallpaper"])),
+        }
+    }
+
+    /// Get the metadata of episode with id ep_id.
+    pub async fn get_episode_metadata(&self, ep_id: &str) -> Result<EpisodeMetadata> {
+        let url = Self::construct_item_query_url(ep_id);
+        let response = http::send(&self.net, self.net.get(url)).await?;
+        let status = response.status();
+        if status != StatusCode::OK {
+            return Err(format!("Status code was not 200 OK.\nCode: {}", status).into());
+        }
+        let text = response.text().await?;
+        let json: Value = serde_json::from_str(&text)?;
+        Ok(Self::parse_episode_metadata(&json))
+    }
+
+    /// Get EpisodeInfo from url, with its metadata if it could be fetched.
+    pub async fn get_episode_details(&self, url: &str) -> Result<EpisodeInfo> {
+        let mut info = self.get_episode_info(url).await?;
+        match self.get_episode_metadata(&info.id).await {
+            Ok(metadata) => info.metadata = metadata,
+            Err(e) => tracing::warn!(id = %info.id, error = %e, "could not get episode metadata"),
+        }
+        Ok(info)
+    }
+
     /// Get a Vec of episode data urls from url.
     pub async fn get_show_episodes(&self, show_url: &str) -> Result<Vec<String>> {
         let url = Self::construct_show_query_url(show_url)?;
-        let response = self.net.get(url).send().await?;
+        let response = http::send(&self.net, self.net.get(url)).await?;
         let text = response.text().await?;
         let json: Value = serde_json::from_str(&text)?;
         let playlist = &json["item"];
@@ -156,22 +241,73 @@ impl Requester {
     }
 
     /// Get data url for episode with id ep_id.
-    #[async_recursion::async_recursion]
-    pub async fn get_episode_url<'b>(&self, ep_id: &str) -> Result<String> {
-        let url = Self::construct_ep_query_url(ep_id).await?;
-        let token = get_or_set_token(|| Requester::get_auth_token(&self.net)).await?;
-        let result = self.net.get(url).bearer_auth(token).send().await;
+    pub async fn get_episode_url(&self, ep_id: &str) -> Result<String> {
+        Ok(self.get_episode_stream(ep_id).await?.url)
+    }
+
+    /// Get the stream and subtitles of episode with id ep_id.
+    pub async fn get_episode_stream(&self, ep_id: &str) -> Result<EpisodeStream> {
+        self.get_episode_stream_retry(ep_id, 0).await
+    }
 
-        if let Err(e) = &result {
-            println!("{:?}", e);
+    /// Get the ISO 639-2 code of a DR subtitle language, such as "DanishLanguageSubtitles".
+    fn parse_subtitle_language(language: &str) -> Option<String> {
+        let language = language.to_lowercase();
+        let code = match language.as_str() {
+            x if x.starts_with("da") || x.contains("danish") || x.contains("foreign") => "dan",
+            x if x.starts_with("en") => "eng",
+            x if x.starts_with("de") || x.contains("german") => "ger",
+            x if x.starts_with("sv") || x.contains("swedish") => "swe",
+            x if x.starts_with("no") || x.contains("norwegian") => "nor",
+            _ => return None,
+        };
+        Some(code.to_owned())
+    }
+
+    fn parse_subtitles(root: &Value) -> Vec<SubtitleTrack> {
+        let mut subtitles = root["subtitles"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|x| {
+                let url = x["link"].as_str().or_else(|| x["url"].as_str())?;
+                let language = x["language"].as_str().unwrap_or_default();
+                let kind = x["type"].as_str().unwrap_or_default();
+                // Foreign language subtitles only cover the parts not spoken in Danish.
+                let forced = language.contains("Foreign") || kind.contains("Foreign");
+                Some(SubtitleTrack {
+                    url: url.to_owned(),
+                    language: Self::parse_subtitle_language(language),
+                    name: Some(kind)
+                        .filter(|x| !x.is_empty())
+                        .or(Some(language).filter(|x| !x.is_empty()))
+                        .map(str::to_owned),
+                    default: false,
+                    forced,
+                })
+            })
+            .collect::<Vec<_>>();
+        if let Some(first) = subtitles.iter_mut().find(|x| !x.forced) {
+            first.default = true;
         }
+        subtitles
+    }
 
-        let result = result?;
+    #[async_recursion::async_recursion]
+    async fn get_episode_stream_retry(&self, ep_id: &str, retry: u32) -> Result<EpisodeStream> {
+        let url = Self::construct_ep_query_url(ep_id).await?;
+        let token = get_or_set_token(|| Requester::get_auth_token(&self.net)).await?;
+        let request = self.net.get(url).bearer_auth(token);
+        let result = http::send_retry(&self.net, request, retry).await?;
 
         let status = result.status();
         if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            if retry >= MAX_AUTH_RETRIES {
+                return Err(format!("Could not authorize after {} retries.", retry).into());
+            }
+            tracing::info!(%status, retry, "refreshing token");
             self.refresh_token().await?;
-            return self.get_episode_url(ep_id).await;
+            return self.get_episode_stream_retry(ep_id, retry + 1).await;
         }
         if status != StatusCode::OK {
             return Err(format!("Status code was not 200 OK.\nCode: {}", status).into());
@@ -183,6 +319,35 @@ impl Requester {
         let ep_url = root["url"]
             .as_str()
             .ok_or_generic("Could not get 'url' from root as str.")?;
-        Ok(ep_url.to_owned())
+        Ok(EpisodeStream {
+            url: ep_url.to_owned(),
+            subtitles: Self::parse_subtitles(root),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parse_episode_metadata_keeps_the_day_of_air_dates() {
+        let item = json!({
+            "releaseDate": "2024-01-05T20:00:00Z",
+            "season": { "seasonNumber": 2, "show": { "title": "Show" } },
+            "episodeNumber": 3,
+        });
+        let meta = Requester::parse_episode_metadata(&item);
+        assert_eq!(meta.air_date.as_deref(), Some("2024-01-05"));
+        assert_eq!(meta.show.as_deref(), Some("Show"));
+        assert_eq!((meta.season, meta.episode), (Some(2), Some(3)));
+    }
+
+    #[test]
+    fn parse_episode_metadata_skips_dates_cut_inside_a_character() {
+        let item = json!({ "releaseDate": "2024-01-0æ", "releaseYear": 2024 });
+        let meta = Requester::parse_episode_metadata(&item);
+        assert_eq!(meta.air_date.as_deref(), Some("2024"));
     }
 }
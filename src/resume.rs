@@ -0,0 +1,273 @@
+use crate::error::{OkOrGeneric, Result};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+/// A finished segment and the byte offset the part file ended at after writing it.
+#[derive(Clone, Copy, Debug)]
+pub struct SegmentState {
+    pub index: usize,
+    pub end_offset: u64,
+}
+
+/// Resume state of a partially downloaded stream, stored next to its part file.
+#[derive(Clone, Debug, Default)]
+pub struct ResumeState {
+    /// The id of the episode the part file belongs to.
+    pub episode_id: String,
+    /// The media playlist being downloaded, without its query so a renewed token still matches.
+    pub variant: String,
+    pub segment_count: usize,
+    pub segments: Vec<SegmentState>,
+    pub complete: bool,
+}
+
+/// Get the path of the part file used while writing to path.
+pub fn part_path(path: impl AsRef<Path>) -> PathBuf {
+    let mut part = path.as_ref().as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+/// Get the path of the part file the unconverted stream is downloaded to when writing to path.
+pub fn source_path(path: impl AsRef<Path>) -> PathBuf {
+    let mut source = path.as_ref().as_os_str().to_owned();
+    source.push(".src");
+    part_path(source)
+}
+
+/// Get the path of the resume state of a part file.
+pub fn state_path(part_path: impl AsRef<Path>) -> PathBuf {
+    let mut state = part_path.as_ref().as_os_str().to_owned();
+    state.push(".resume");
+    PathBuf::from(state)
+}
+
+/// Get the id of the episode a part file belongs to, if it has resume state.
+pub fn episode_id(part_path: impl AsRef<Path>) -> Option<String> {
+    let text = std::fs::read_to_string(state_path(part_path)).ok()?;
+    ResumeState::parse(&text).ok().map(|x| x.episode_id)
+}
+
+impl ResumeState {
+    pub fn new(episode_id: &str, variant_uri: &str, segment_count: usize) -> Self {
+        let variant = variant_uri.split('?').next().unwrap_or_default();
+        ResumeState {
+            episode_id: episode_id.to_owned(),
+            variant: variant.to_owned(),
+            segment_count,
+            ..Default::default()
+        }
+    }
+
+    /// Returns true if this state continues the download described by other, a new state.
+    pub fn continues(&self, other: &ResumeState) -> bool {
+        self.episode_id == other.episode_id
+            && self.variant == other.variant
+            && self.segment_count == other.segment_count
+    }
+
+    /// Returns true if the part file holds the segments this state says are done.
+    /// It may be longer than the resume offset while incomplete, as a segment can be cut off while it is written.
+    pub async fn matches_part_file(&self, part_path: impl AsRef<Path>) -> bool {
+        let len = match tokio::fs::metadata(part_path).await {
+            Ok(x) => x.len(),
+            Err(_) => return false,
+        };
+        if self.complete {
+            len == self.resume_offset()
+        } else {
+            len >= self.resume_offset()
+        }
+    }
+
+    /// Load the resume state of a part file, if there is any.
+    pub async fn load(part_path: impl AsRef<Path>) -> Option<Self> {
+        let text = tokio::fs::read_to_string(state_path(part_path))
+            .await
+            .ok()?;
+        Self::parse(&text).ok()
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let json: Value = serde_json::from_str(text)?;
+        let episode_id = json["episode_id"]
+            .as_str()
+            .ok_or_generic("Could not get episode_id from resume state.")?;
+        let variant = json["variant"]
+            .as_str()
+            .ok_or_generic("Could not get variant from resume state.")?;
+        let segment_count = json["segment_count"]
+            .as_u64()
+            .ok_or_generic("Could not get segment_count from resume state.")?
+            as usize;
+        let segments = json["segments"]
+            .as_array()
+            .ok_or_generic("Could not get segments from resume state.")?
+            .iter()
+            .map(|x| {
+                Some(SegmentState {
+                    index: x["index"].as_u64()? as usize,
+                    end_offset: x["end_offset"].as_u64()?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_generic("Resume state contained an invalid segment.")?;
+        Ok(ResumeState {
+            episode_id: episode_id.to_owned(),
+            variant: variant.to_owned(),
+            segment_count,
+            segments,
+            complete: json["complete"].as_bool().unwrap_or(false),
+        })
+    }
+
+    /// Write the resume state of a part file.
+    pub async fn save(&self, part_path: impl AsRef<Path>) -> Result<()> {
+        let segments = self
+            .segments
+            .iter()
+            .map(|x| json!({ "index": x.index, "end_offset": x.end_offset }))
+            .collect::<Vec<_>>();
+        let json = json!({
+            "episode_id": self.episode_id,
+            "variant": self.variant,
+            "segment_count": self.segment_count,
+            "segments": segments,
+            "complete": self.complete,
+        });
+        let state_path = state_path(part_path);
+        let tmp_path = part_path_tmp(&state_path);
+        tokio::fs::write(&tmp_path, json.to_string()).await?;
+        tokio::fs::rename(tmp_path, state_path).await?;
+        Ok(())
+    }
+
+    /// Remove the resume state of a part file.
+    pub async fn remove(part_path: impl AsRef<Path>) -> Result<()> {
+        match tokio::fs::remove_file(state_path(part_path)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Get the index of the next segment to download.
+    pub fn next_segment(&self) -> usize {
+        self.segments.last().map(|x| x.index + 1).unwrap_or(0)
+    }
+
+    /// Get the offset the part file should be truncated to before resuming.
+    pub fn resume_offset(&self) -> u64 {
+        self.segments.last().map(|x| x.end_offset).unwrap_or(0)
+    }
+
+    pub fn mark_done(&mut self, index: usize, end_offset: u64) {
+        self.segments.push(SegmentState { index, end_offset });
+        self.complete = self.segments.len() == self.segment_count;
+    }
+}
+
+fn part_path_tmp(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VARIANT: &str = "https://cdn.example.com/high/index.m3u8";
+
+    /// Get a part file path of its own in the temporary directory, without the files a previous run left.
+    fn part_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "dr-downloader-{}-{}.mp4.part",
+            name,
+            std::process::id()
+        ));
+        remove_part_file(&path);
+        path
+    }
+
+    fn remove_part_file(path: &Path) {
+        std::fs::remove_file(path).ok();
+        std::fs::remove_file(state_path(path)).ok();
+    }
+
+    #[test]
+    fn continues_the_same_episode_and_variant() {
+        let state = ResumeState::new("123", &format!("{}?token=abc", VARIANT), 10);
+        assert_eq!(state.variant, VARIANT);
+        // A renewed token does not start the download over.
+        assert!(state.continues(&ResumeState::new(
+            "123",
+            &format!("{}?token=def", VARIANT),
+            10
+        )));
+        assert!(!state.continues(&ResumeState::new("456", VARIANT, 10)));
+        assert!(!state.continues(&ResumeState::new(
+            "123",
+            "https://cdn.example.com/low/index.m3u8",
+            10
+        )));
+        assert!(!state.continues(&ResumeState::new("123", VARIANT, 11)));
+    }
+
+    #[test]
+    fn tracks_the_next_segment_and_resume_offset() {
+        let mut state = ResumeState::new("123", VARIANT, 2);
+        assert_eq!(state.next_segment(), 0);
+        assert_eq!(state.resume_offset(), 0);
+        state.mark_done(0, 100);
+        assert_eq!(state.next_segment(), 1);
+        assert_eq!(state.resume_offset(), 100);
+        assert!(!state.complete);
+        state.mark_done(1, 250);
+        assert_eq!(state.next_segment(), 2);
+        assert_eq!(state.resume_offset(), 250);
+        assert!(state.complete);
+    }
+
+    #[tokio::test]
+    async fn saves_and_loads_state() {
+        let part = part_file("resume-round-trip");
+        assert!(ResumeState::load(&part).await.is_none());
+        let mut state = ResumeState::new("123", VARIANT, 3);
+        state.mark_done(0, 100);
+        state.mark_done(1, 250);
+        state.save(&part).await.unwrap();
+
+        let loaded = ResumeState::load(&part).await.unwrap();
+        assert!(loaded.continues(&state));
+        assert_eq!(loaded.next_segment(), 2);
+        assert_eq!(loaded.resume_offset(), 250);
+        assert!(!loaded.complete);
+        assert_eq!(episode_id(&part).as_deref(), Some("123"));
+
+        ResumeState::remove(&part).await.unwrap();
+        assert!(ResumeState::load(&part).await.is_none());
+        // Removing state that is already gone is fine.
+        ResumeState::remove(&part).await.unwrap();
+        remove_part_file(&part);
+    }
+
+    #[tokio::test]
+    async fn matches_part_file_by_length() {
+        let part = part_file("resume-part-length");
+        let mut state = ResumeState::new("123", VARIANT, 2);
+        state.mark_done(0, 4);
+        assert!(!state.matches_part_file(&part).await);
+        std::fs::write(&part, b"abc").unwrap();
+        assert!(!state.matches_part_file(&part).await);
+        std::fs::write(&part, b"abcd").unwrap();
+        assert!(state.matches_part_file(&part).await);
+        // Part of the next segment was written before being cut off.
+        std::fs::write(&part, b"abcdef").unwrap();
+        assert!(state.matches_part_file(&part).await);
+        state.mark_done(1, 8);
+        assert!(!state.matches_part_file(&part).await);
+        std::fs::write(&part, b"abcdefgh").unwrap();
+        assert!(state.matches_part_file(&part).await);
+        remove_part_file(&part);
+    }
+}
@@ -1,100 +1,992 @@
-use crate::converter::Converter;
+use crate::batch::{read_url_list, verify_out_dir, BatchItem};
+use crate::converter::{ConvertOptions, Converter, CoverArt};
 use crate::downloader::Downloader;
 use crate::error::ok_or_generic::OkOrGeneric;
-use crate::error::Result;
+use crate::error::{is_cancelled, Result};
+use crate::filename::FilenameOptions;
 use crate::format::Format;
-use crate::models::URLType;
-use crate::util::{legalize_filename, remove_newline_string};
-use std::path;
+use crate::hls::StreamSelection;
+use crate::info_json::{self, SavedEpisode};
+use crate::job::JobHandle;
+use crate::models::episode::{EpisodeInfo, EpisodeStream};
+use crate::models::{DownloadEvent, SubtitleTrack, URLType, VariantInfo};
+use crate::nfo;
+use crate::profile::{ConversionProfile, SourceCodecs, VideoCodec};
+use crate::remux;
+use crate::report::{EpisodeOutcome, EpisodeReport, SaveReport};
+use crate::resume::{self, ResumeState};
+use crate::template::PathTemplate;
+use crate::util::{image_mime_type, redact_url, remove_newline_string};
+use futures::{Stream, StreamExt};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+use tokio::io::AsyncReadExt;
+use tracing::Instrument;
 
-const DEFAULT_FORMAT: Format = Format::from_exact_extension(".mp4");
+const DEFAULT_FORMAT: Format = Format::Mp4;
+const DEFAULT_AUDIO_FORMAT: Format = Format::M4a;
+
+/// What a Saver does when the file of an episode already exists.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// Keep the existing file and skip the episode, as an existing file is always complete.
+    /// If the info.json next to the file names another episode, the episode is saved under a new name as with Rename.
+    #[default]
+    Skip,
+    /// Replace the existing file once the new one is complete.
+    Overwrite,
+    /// Save to the first free path with a counter appended, such as "name (1).mp4".
+    Rename,
+    /// Fail the episode.
+    Error,
+}
 
 /// A utility for downloading media to a path.
 #[derive(Clone)]
-pub struct Saver<'a> {
-    downloader: Downloader<'a>,
-    converter: Option<Converter<'a>>,
+pub struct Saver {
+    downloader: Downloader,
+    converter: Option<Converter>,
+    keep_partial: bool,
+    audio_only: bool,
+    embed_subtitles: bool,
+    embed_cover_art: bool,
+    template: PathTemplate,
+    media_server: bool,
+    info_json: bool,
+    overwrite: OverwritePolicy,
+    filename_options: FilenameOptions,
+    concurrency: usize,
+    claims: Arc<Mutex<HashMap<PathBuf, String>>>,
+}
+
+/// A path an episode is being saved to, with the id of the episode.
+/// It is released when dropped, so episodes saved at once never write to the same path.
+struct PathClaim {
+    claims: Arc<Mutex<HashMap<PathBuf, String>>>,
+    path: PathBuf,
+}
+
+impl Drop for PathClaim {
+    fn drop(&mut self) {
+        lock_claims(&self.claims).remove(&self.path);
+    }
+}
+
+fn lock_claims(
+    claims: &Mutex<HashMap<PathBuf, String>>,
+) -> MutexGuard<'_, HashMap<PathBuf, String>> {
+    claims
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Where an episode is saved.
+enum Destination {
+    Save(PathClaim),
+    /// The file of the episode already exists at the path.
+    Skip(PathBuf),
 }
 
-impl<'a> Saver<'a> {
-    pub fn new(downloader: Downloader<'a>) -> Self {
+/// An episode to save, and where.
+struct EpisodeJob {
+    url: String,
+    out_dir: String,
+    format: Format,
+}
+
+/// What write_episode wrote.
+struct WrittenEpisode {
+    stream: EpisodeStream,
+    variant: VariantInfo,
+    subtitles_embedded: bool,
+}
+
+impl Saver {
+    pub fn new(downloader: Downloader) -> Self {
         Saver {
             downloader,
             converter: None,
+            keep_partial: true,
+            audio_only: false,
+            embed_subtitles: false,
+            embed_cover_art: false,
+            template: PathTemplate::default(),
+            media_server: false,
+            info_json: false,
+            overwrite: OverwritePolicy::default(),
+            filename_options: FilenameOptions::default(),
+            concurrency: 1,
+            claims: Arc::default(),
         }
     }
 
-    pub fn with_converter(mut self, converter: Converter<'a>) -> Self {
+    pub fn with_converter(mut self, converter: Converter) -> Self {
         self.converter = Some(converter);
         self
     }
 
-    async fn save_ep<'b>(
+    pub fn get_downloader(&self) -> &Downloader {
+        &self.downloader
+    }
+
+    /// Get a stream of the download events of this Saver.
+    pub fn event_stream(&self) -> impl Stream<Item = DownloadEvent> + Send + 'static {
+        self.downloader.event_stream()
+    }
+
+    /// Set whether partial output is kept when a job is cancelled, so it can be resumed later. Defaults to true.
+    pub fn keep_partial_on_cancel(mut self, keep: bool) -> Self {
+        self.keep_partial = keep;
+        self
+    }
+
+    /// Set whether only the audio is saved. Requires a Converter.
+    /// Only audio segments are downloaded if the stream has an audio only rendition or variant,
+    /// otherwise the video variant with the lowest bandwidth is downloaded and its audio extracted.
+    /// The format defaults to m4a, and the audio is encoded to suit m4a, mp3 or opus.
+    pub fn audio_only(mut self, audio_only: bool) -> Self {
+        self.audio_only = audio_only;
+        self
+    }
+
+    /// Set whether subtitles are muxed into the output as soft tracks. Requires a Converter. Defaults to false.
+    /// Subtitles are written as mov_text in MP4 and SRT in MKV, and left out of formats without subtitle support and audio only output.
+    pub fn embed_subtitles(mut self, embed: bool) -> Self {
+        self.embed_subtitles = embed;
+        self
+    }
+
+    /// Set whether the poster of the episode is embedded as cover art. Requires a Converter. Defaults to false.
+    pub fn embed_cover_art(mut self, embed: bool) -> Self {
+        self.embed_cover_art = embed;
+        self
+    }
+
+    /// Set the template for the paths of saved episodes, relative to the output directory.
+    /// Defaults to naming files after the name in the episode url, see PathTemplate for the syntax.
+    pub fn with_template(mut self, template: PathTemplate) -> Self {
+        self.template = template;
+        self
+    }
+
+    /// Set whether shows are laid out for media servers such as Plex, Jellyfin and Kodi. Defaults to false.
+    /// Enabling it sets the template to MEDIA_SERVER_TEMPLATE, which with_template can change afterwards.
+    /// A tvshow.nfo, poster and fanart are written in the show directory, the first directory of the template,
+    /// and an NFO and thumbnail next to each episode. Existing files are kept.
+    pub fn media_server_layout(mut self, enabled: bool) -> Self {
+        self.media_server = enabled;
+        if enabled {
+            self.template = PathTemplate::media_server();
+        }
+        self
+    }
+
+    /// Set whether a <name>.info.json is written next to each saved episode. Defaults to false.
+    /// It holds the metadata of the episode, the stream it was downloaded from, when, and the checksum of the file.
+    pub fn write_info_json(mut self, enabled: bool) -> Self {
+        self.info_json = enabled;
+        self
+    }
+
+    /// Set what happens when the file of an episode already exists. Defaults to skipping the episode.
+    /// Every file is written to a part file first and renamed into place once complete, so nothing is replaced by partial output.
+    /// Episodes saved at once that would be written to the same path are saved under new names whatever the policy.
+    pub fn with_overwrite_policy(mut self, policy: OverwritePolicy) -> Self {
+        self.overwrite = policy;
+        self
+    }
+
+    /// Set how the directories and filenames given by the template are made legal.
+    /// Defaults to the rules of the operating system this is compiled for, without transliteration.
+    pub fn with_filename_options(mut self, options: FilenameOptions) -> Self {
+        self.filename_options = options;
+        self
+    }
+
+    /// Set how many episodes are saved at once, across a show or a whole batch. Defaults to 1.
+    pub fn max_concurrent_episodes(mut self, max: usize) -> Self {
+        self.concurrency = max.max(1);
+        self
+    }
+
+    fn get_selection(&self) -> StreamSelection {
+        match self.audio_only {
+            true => StreamSelection::AudioOnly,
+            false => StreamSelection::Best,
+        }
+    }
+
+    /// Get the Converter to use for format, with its profile replaced in audio only mode unless it already leaves out video.
+    fn get_converter(&self, format: &Format) -> Option<Cow<'_, Converter>> {
+        let con = self.converter.as_ref()?;
+        if !self.audio_only || con.get_profile().get_video_codec() == VideoCodec::Disabled {
+            return Some(Cow::Borrowed(con));
+        }
+        let profile = ConversionProfile::for_audio_format(format);
+        Some(Cow::Owned(con.clone().with_profile(profile)))
+    }
+
+    /// Get the path an attachment of the output at path is downloaded to before it is muxed in.
+    fn attachment_path(path: &Path, name: &str) -> PathBuf {
+        let mut path = path.as_os_str().to_owned();
+        path.push(format!(".{}.part", name));
+        path.into()
+    }
+
+    /// Download the subtitles and cover art to embed into the output at path, adding them to options.
+    /// Attachments that fail to download are left out. Returns the paths of the downloaded files.
+    async fn download_attachments(
         &self,
-        ep_url: String,
-        out_dir: impl AsRef<str>,
-        format: Format<'b>,
-    ) -> Result<()> {
-        let out_dir = out_dir.as_ref();
+        episode: &EpisodeInfo,
+        subtitles: &[SubtitleTrack],
+        path: &Path,
+        options: &mut ConvertOptions,
+    ) -> Vec<PathBuf> {
+        let mut files = vec![];
+        let poster_url = episode
+            .metadata
+            .poster_url
+            .as_ref()
+            .filter(|_| self.embed_cover_art);
+        let subtitles = subtitles
+            .iter()
+            .filter(|_| self.embed_subtitles && !self.audio_only);
+        let downloads = subtitles
+            .enumerate()
+            .map(|(i, x)| (format!("sub{}", i), &x.url, Some(x)))
+            .chain(poster_url.map(|x| ("cover".to_owned(), x, None)));
+        for (name, url, subtitle) in downloads {
+            let data = match self.downloader.get_as_bytes(url).await {
+                Ok(x) => x,
+                Err(e) => {
+                    tracing::warn!(url = %redact_url(url), error = %e, "could not download attachment");
+                    continue;
+                }
+            };
+            let file = Self::attachment_path(path, &name);
+            if let Err(e) = tokio::fs::write(&file, &data).await {
+                tracing::warn!(path = %file.display(), error = %e, "could not write attachment");
+                continue;
+            }
+            let file_str = file.to_string_lossy().into_owned();
+            match subtitle {
+                Some(x) => options.subtitles.push(SubtitleTrack {
+                    url: file_str,
+                    ..x.clone()
+                }),
+                None => {
+                    options.cover = Some(CoverArt {
+                        path: file_str,
+                        mime_type: image_mime_type(&data).to_owned(),
+                    })
+                }
+            }
+            files.push(file);
+        }
+        files
+    }
+
+    async fn remove_attachments(files: &[PathBuf]) {
+        for file in files {
+            tokio::fs::remove_file(file).await.ok();
+        }
+    }
+
+    /// Write data to path through a part file, so an existing file is always complete.
+    async fn write_file(path: &Path, data: &[u8]) -> Result<()> {
+        let part = resume::part_path(path);
+        tokio::fs::write(&part, data).await?;
+        tokio::fs::rename(&part, path).await?;
+        Ok(())
+    }
+
+    async fn write_new_file(path: &Path, data: &[u8]) -> Result<()> {
+        match path.exists() {
+            true => Ok(()),
+            false => Self::write_file(path, data).await,
+        }
+    }
+
+    /// Download the image at url to name in dir, with the extension of its type, unless it already exists.
+    async fn save_image(&self, url: Option<&String>, dir: &Path, name: &str) -> Result<()> {
+        const EXTENSIONS: &[&str] = &["jpg", "png", "webp", "gif"];
+        let url = match url {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        let path = |ext: &str| dir.join(format!("{}.{}", name, ext));
+        if EXTENSIONS.iter().any(|x| path(x).exists()) {
+            return Ok(());
+        }
+        let data = self.downloader.get_as_bytes(url).await?;
+        let ext = match image_mime_type(&data) {
+            "image/png" => "png",
+            "image/webp" => "webp",
+            "image/gif" => "gif",
+            _ => "jpg",
+        };
+        Self::write_file(&path(ext), &data).await
+    }
+
+    /// Write the files media servers read next to the episode at path, relative_path being the path below out_dir.
+    /// Failures are logged rather than failing the episode, which is already saved.
+    async fn write_library_files(
+        &self,
+        episode: &EpisodeInfo,
+        path: &Path,
+        out_dir: &Path,
+        relative_path: &Path,
+    ) {
+        let meta = &episode.metadata;
+        // The episode is in the show directory itself if the template has no directories.
+        let show_dir = match relative_path.iter().count() {
+            1 => out_dir.to_owned(),
+            _ => out_dir.join(relative_path.iter().next().unwrap_or_default()),
+        };
+        let dir = path.parent().unwrap_or(out_dir);
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let nfo_path = path.with_extension("nfo");
+        let nfo = nfo::episode(episode);
+        // The NFO describes the episode file, so it is replaced along with it.
+        let episode_nfo = match self.overwrite {
+            OverwritePolicy::Overwrite => Self::write_file(&nfo_path, nfo.as_bytes()).await,
+            _ => Self::write_new_file(&nfo_path, nfo.as_bytes()).await,
+        };
+        let results = [
+            (
+                "tvshow.nfo",
+                Self::write_new_file(
+                    &show_dir.join("tvshow.nfo"),
+                    nfo::tvshow(episode).as_bytes(),
+                )
+                .await,
+            ),
+            (
+                "poster",
+                self.save_image(
+                    meta.show_poster_url.as_ref().or(meta.poster_url.as_ref()),
+                    &show_dir,
+                    "poster",
+                )
+                .await,
+            ),
+            (
+                "fanart",
+                self.save_image(meta.fanart_url.as_ref(), &show_dir, "fanart")
+                    .await,
+            ),
+            ("episode nfo", episode_nfo),
+            (
+                "thumbnail",
+                self.save_image(meta.poster_url.as_ref(), dir, &format!("{}-thumb", stem))
+                    .await,
+            ),
+        ];
+        for (file, result) in results {
+            if let Err(e) = result {
+                tracing::warn!(file, error = %e, "could not write library file");
+            }
+        }
+    }
+
+    async fn save_info_json(saved: &SavedEpisode<'_>) -> Result<()> {
+        let json = info_json::build(saved).await?;
+        Self::write_file(&info_json::info_path(saved.path), json.as_bytes()).await
+    }
+
+    /// Returns true if no other episode has partial output at path, so episode_id can write or resume it.
+    fn partial_output_free(path: &Path, episode_id: &str) -> bool {
+        let parts = [resume::part_path(path), resume::source_path(path)];
+        if !parts.iter().any(|x| x.exists()) {
+            return true;
+        }
+        parts
+            .iter()
+            .filter_map(resume::episode_id)
+            .any(|x| x == episode_id)
+    }
+
+    /// Get the first path with a counter appended that neither a file, another episode being saved,
+    /// nor partial output of another episode is using. Partial output of the episode itself is resumed.
+    fn free_path(path: &Path, episode_id: &str, claims: &HashMap<PathBuf, String>) -> PathBuf {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let ext = path.extension().map(|x| x.to_string_lossy());
+        (1..)
+            .map(|i| {
+                let name = match &ext {
+                    Some(ext) => format!("{} ({}).{}", stem, i, ext),
+                    None => format!("{} ({})", stem, i),
+                };
+                path.with_file_name(name)
+            })
+            .find(|x| {
+                !claims.contains_key(x) && !x.exists() && Self::partial_output_free(x, episode_id)
+            })
+            .unwrap_or_else(|| path.to_owned())
+    }
+
+    /// Decide where the episode is saved under the overwrite policy, and claim the path until the claim is dropped.
+    fn claim_path(&self, episode: &EpisodeInfo, path: PathBuf) -> Result<Destination> {
+        let mut claims = lock_claims(&self.claims);
+        let id = &episode.id;
+        let path = match self.overwrite {
+            _ if claims.contains_key(&path) => {
+                let free = Self::free_path(&path, id, &claims);
+                tracing::warn!(path = %path.display(), free = %free.display(), "another episode is being saved to the path, saving under a new name");
+                free
+            }
+            _ if !path.exists() => path,
+            OverwritePolicy::Overwrite => {
+                tracing::info!(path = %path.display(), "overwriting existing file");
+                path
+            }
+            OverwritePolicy::Rename => {
+                let free = Self::free_path(&path, id, &claims);
+                tracing::info!(path = %free.display(), "file exists, saving under a new name");
+                free
+            }
+            OverwritePolicy::Error => {
+                return Err(format!("{} already exists.", path.display()).into());
+            }
+            OverwritePolicy::Skip => match info_json::episode_id(&path) {
+                Some(x) if x != *id => {
+                    let free = Self::free_path(&path, id, &claims);
+                    tracing::warn!(path = %path.display(), other = %x, free = %free.display(), "file of another episode exists, saving under a new name");
+                    free
+                }
+                _ => return Ok(Destination::Skip(path)),
+            },
+        };
+        claims.insert(path.clone(), id.clone());
+        Ok(Destination::Save(PathClaim {
+            claims: self.claims.clone(),
+            path,
+        }))
+    }
+
+    async fn remove_partial(path: &Path) {
+        let part = resume::part_path(path);
+        let source = resume::source_path(path);
+        for part in [part, source] {
+            tokio::fs::remove_file(&part).await.ok();
+            ResumeState::remove(&part).await.ok();
+        }
+    }
+
+    /// Download the episode to the path given by the template, through its part file, then convert or move it into place.
+    /// The finished file only ever appears through a rename, so an existing file is always complete.
+    #[tracing::instrument(name = "episode", skip_all, fields(id = %ep_info.id, name = %ep_info.name))]
+    async fn save_info(
+        &self,
+        ep_info: EpisodeInfo,
+        out_dir: &str,
+        format: &Format,
+        handle: &JobHandle,
+        start: Instant,
+    ) -> Result<EpisodeOutcome> {
+        let out_dir = Path::new(out_dir);
+        let relative_path = match self
+            .template
+            .render(&ep_info, format, &self.filename_options)
+        {
+            Ok(x) => x,
+            Err(e) => return Err(self.downloader.report_error(&ep_info, e)),
+        };
+        let claim = match self.claim_path(&ep_info, out_dir.join(&relative_path)) {
+            Ok(Destination::Save(x)) => x,
+            Ok(Destination::Skip(path)) => {
+                tracing::info!(path = %path.display(), "skipping existing file");
+                if self.media_server {
+                    self.write_library_files(&ep_info, &path, out_dir, &relative_path)
+                        .await;
+                }
+                let reason = "File already exists.".to_owned();
+                self.downloader.emit(DownloadEvent::Skipped {
+                    episode: ep_info,
+                    reason: reason.clone(),
+                });
+                return Ok(EpisodeOutcome::Skipped { reason });
+            }
+            Err(e) => return Err(self.downloader.report_error(&ep_info, e)),
+        };
+        let path = claim.path.clone();
+
+        let written = match self.write_episode(&ep_info, &path, format, handle).await {
+            Ok(x) => x,
+            Err(e) => {
+                if is_cancelled(e.as_ref()) && !self.keep_partial {
+                    Self::remove_partial(&path).await;
+                }
+                return Err(self.downloader.report_error(&ep_info, e));
+            }
+        };
+        if self.info_json {
+            let saved = SavedEpisode {
+                episode: &ep_info,
+                stream: &written.stream,
+                variant: &written.variant,
+                subtitles_embedded: written.subtitles_embedded,
+                format,
+                path: &path,
+            };
+            if let Err(e) = Self::save_info_json(&saved).await {
+                tracing::warn!(error = %e, "could not write info.json");
+            }
+        }
+        let bytes = tokio::fs::metadata(&path).await?.len();
+        tracing::info!(path = %path.display(), bytes, "episode saved");
+        if self.media_server {
+            self.write_library_files(&ep_info, &path, out_dir, &relative_path)
+                .await;
+        }
+        self.downloader.emit(DownloadEvent::Finished {
+            episode: ep_info,
+            path: Some(path.clone()),
+            bytes,
+        });
+        Ok(EpisodeOutcome::Saved {
+            path: Some(path),
+            bytes,
+            elapsed: start.elapsed(),
+        })
+    }
+
+    async fn write_episode(
+        &self,
+        episode: &EpisodeInfo,
+        path: &Path,
+        format: &Format,
+        handle: &JobHandle,
+    ) -> Result<WrittenEpisode> {
         let requester = self.downloader.get_requester();
+        let stream = requester.get_episode_stream(&episode.id).await?;
+        let stream_url = stream.url.clone();
+        let downloader = &self.downloader;
+        downloader.emit(DownloadEvent::Resolved {
+            episode: episode.clone(),
+            stream_url: stream_url.clone(),
+        });
+        downloader.emit(DownloadEvent::Started {
+            episode: episode.clone(),
+        });
 
-        let ep_info = requester.get_episode_info(&ep_url).await?;
-        let ep_url = requester.get_episode_url(&ep_info.id).await?;
-        let mut path = path::PathBuf::from(out_dir);
-        let legal_name = crate::util::legalize_filename(&ep_info.name);
-        path.push(format!("{}{}", legal_name, format.get_extension()));
-        if let Some(con) = &self.converter {
-            con.convert(ep_url, path.to_str().ok_or_generic("Path was invalid.")?)?;
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let selection = self.get_selection();
+        if let Some(con) = self.get_converter(format) {
+            let source = resume::source_path(path);
+            let download = self
+                .downloader
+                .download_stream(episode, &stream_url, &source, selection, handle)
+                .await?;
+            downloader.emit(DownloadEvent::ConvertStarted {
+                episode: episode.clone(),
+                path: path.to_owned(),
+            });
+            let mut options = ConvertOptions {
+                duration: Some(download.duration),
+                tags: episode.tags(),
+                source: download
+                    .variant
+                    .codecs
+                    .as_deref()
+                    .map(SourceCodecs::parse)
+                    .unwrap_or_default(),
+                ..Default::default()
+            };
+            let attachments = self
+                .download_attachments(episode, &stream.subtitles, path, &mut options)
+                .await;
+            let result = con
+                .convert(
+                    source.to_str().ok_or_generic("Path was invalid.")?,
+                    path.to_str().ok_or_generic("Path was invalid.")?,
+                    format,
+                    &options,
+                    handle,
+                    |progress| {
+                        downloader.emit(DownloadEvent::ConvertProgress {
+                            episode: episode.clone(),
+                            progress: progress.clone(),
+                        })
+                    },
+                )
+                .await;
+            Self::remove_attachments(&attachments).await;
+            result?;
+            tokio::fs::remove_file(&source).await?;
+            ResumeState::remove(&source).await?;
+            Ok(WrittenEpisode {
+                subtitles_embedded: !options.subtitles.is_empty()
+                    && format.get_subtitle_codec().is_some(),
+                stream,
+                variant: download.variant,
+            })
+        } else if *format == Format::Ts {
+            let part = resume::part_path(path);
+            let download = self
+                .downloader
+                .download_stream(episode, &stream_url, &part, selection, handle)
+                .await?;
+            let mut start = [0; 1];
+            tokio::fs::File::open(&part)
+                .await?
+                .read_exact(&mut start)
+                .await?;
+            if !remux::is_transport_stream(&start) {
+                return Err("The stream is not an MPEG transport stream, so it cannot be saved as TS without a Converter. Save it as MP4 instead.".into());
+            }
+            tokio::fs::rename(&part, path).await?;
+            ResumeState::remove(&part).await?;
+            Ok(WrittenEpisode {
+                stream,
+                variant: download.variant,
+                subtitles_embedded: false,
+            })
         } else {
-            let ep = self.downloader.download_episode(ep_url).await?;
-            std::fs::write(path, &ep.data)?;
+            // Remuxed into MP4 without FFMPEG, which is where the tags are written.
+            let source = resume::source_path(path);
+            let download = self
+                .downloader
+                .download_stream(episode, &stream_url, &source, selection, handle)
+                .await?;
+            let part = resume::part_path(path);
+            let (input, output, tags, remux_handle) =
+                (source.clone(), part.clone(), episode.tags(), handle.clone());
+            let result = tokio::task::spawn_blocking(move || {
+                remux::remux(&input, &output, &tags, &remux_handle)
+            })
+            .await;
+            if let Err(e) = result.map_err(|e| e.into()).and_then(|x| x) {
+                // The remuxed output cannot be resumed, so the part file is of no use.
+                tokio::fs::remove_file(&part).await.ok();
+                return Err(e);
+            }
+            tokio::fs::rename(&part, path).await?;
+            tokio::fs::remove_file(&source).await?;
+            ResumeState::remove(&source).await?;
+            Ok(WrittenEpisode {
+                stream,
+                variant: download.variant,
+                subtitles_embedded: false,
+            })
         }
-        Ok(())
     }
 
-    async fn save_show<'b>(
+    /// Save the episode at ep_url, recording how it went rather than returning an error.
+    /// Episodes whose id is already in seen are skipped, so an episode listed several times is only saved once.
+    async fn save_ep(
+        &self,
+        ep_url: String,
+        out_dir: &str,
+        format: &Format,
+        handle: &JobHandle,
+        seen: &Mutex<HashSet<String>>,
+    ) -> EpisodeReport {
+        let start = Instant::now();
+        let requester = self.downloader.get_requester();
+        let lookup = async {
+            handle.checkpoint().await?;
+            requester.get_episode_details(&ep_url).await
+        };
+        let ep_info = match lookup.await {
+            Ok(x) => x,
+            Err(e) => {
+                tracing::warn!(url = %ep_url, error = %e, "could not look up episode");
+                return EpisodeReport {
+                    url: ep_url,
+                    episode: None,
+                    out_dir: Some(out_dir.to_owned()),
+                    format: Some(format.clone()),
+                    outcome: EpisodeOutcome::from_error(e),
+                };
+            }
+        };
+        let first = seen
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(ep_info.id.clone());
+        if !first {
+            tracing::debug!(url = %ep_url, "skipping duplicate episode");
+            let reason = "Episode was already listed in the job.".to_owned();
+            self.downloader.emit(DownloadEvent::Skipped {
+                episode: ep_info.clone(),
+                reason: reason.clone(),
+            });
+            return EpisodeReport {
+                url: ep_url,
+                episode: Some(ep_info),
+                out_dir: Some(out_dir.to_owned()),
+                format: Some(format.clone()),
+                outcome: EpisodeOutcome::Skipped { reason },
+            };
+        }
+        let result = self
+            .save_info(ep_info.clone(), out_dir, format, handle, start)
+            .await;
+        EpisodeReport {
+            url: ep_url,
+            episode: Some(ep_info),
+            out_dir: Some(out_dir.to_owned()),
+            format: Some(format.clone()),
+            outcome: EpisodeOutcome::from_result(result),
+        }
+    }
+
+    /// Save the episodes of jobs, up to the concurrency limit at once, continuing past episodes that fail.
+    /// If the job is cancelled, the episodes that were not saved are listed as cancelled.
+    async fn save_episodes(&self, jobs: Vec<EpisodeJob>, handle: &JobHandle) -> SaveReport {
+        let seen = Mutex::new(HashSet::new());
+        let seen = &seen;
+        let episodes = futures::stream::iter(jobs)
+            .map(|job| async move {
+                self.save_ep(job.url, &job.out_dir, &job.format, handle, seen)
+                    .await
+            })
+            .buffered(self.concurrency)
+            .collect()
+            .await;
+        SaveReport { episodes }
+    }
+
+    fn episode_jobs(ep_urls: Vec<String>, out_dir: &str, format: &Format) -> Vec<EpisodeJob> {
+        ep_urls
+            .into_iter()
+            .map(|url| EpisodeJob {
+                url,
+                out_dir: out_dir.to_owned(),
+                format: format.clone(),
+            })
+            .collect()
+    }
+
+    async fn save_show(
         &self,
         show_url: String,
-        out_dir: impl AsRef<str>,
-        format: Format<'b>,
-    ) -> Result<()> {
-        let out_dir = out_dir.as_ref();
+        out_dir: &str,
+        format: &Format,
+        handle: &JobHandle,
+    ) -> Result<SaveReport> {
         let requester = self.downloader.get_requester();
+        let ep_urls = requester.get_show_episodes(&show_url).await?;
+        let jobs = Self::episode_jobs(ep_urls, out_dir, format);
+        Ok(self.save_episodes(jobs, handle).await)
+    }
 
-        for ep_url in requester.get_show_episodes(&show_url).await? {
-            let ep_info = requester.get_episode_info(&ep_url).await?;
+    /// Get the output directory and format of a batch item, falling back to out_dir and format.
+    fn batch_target(
+        &self,
+        item: &BatchItem,
+        out_dir: &str,
+        format: Option<&Format>,
+    ) -> Result<(String, Format)> {
+        let out_dir = match &item.out_dir {
+            Some(x) => {
+                verify_out_dir(x)?;
+                Path::new(out_dir).join(x).to_string_lossy().into_owned()
+            }
+            None => out_dir.to_owned(),
+        };
+        let format = self.resolve_format(item.format.clone().or_else(|| format.cloned()))?;
+        Ok((out_dir, format))
+    }
 
-            let mut path = path::PathBuf::from(&out_dir);
-            let legal_name = legalize_filename(&ep_info.name);
-            path.push(format!("{}{}", legal_name, format.get_extension()));
-            if let Some(con) = &self.converter {
-                con.convert(ep_url, path.to_str().ok_or_generic("Path was invalid.")?)?;
-            } else {
-                let ep = self.downloader.download_episode(ep_url).await?;
-                std::fs::write(path, &ep.data)?;
+    /// Get the episode urls of a batch item, which is either an episode or a show.
+    async fn batch_urls(&self, url: String) -> Result<Vec<String>> {
+        Ok(match URLType::get(&url)? {
+            URLType::Video => vec![url],
+            URLType::Playlist => {
+                let requester = self.downloader.get_requester();
+                requester.get_show_episodes(&url).await?
             }
-        }
-        Ok(())
+        })
     }
 
     fn sanitize_url(url: &mut String) {
         remove_newline_string(url);
     }
 
-    /// Download media to file in directory. If the Saver has no Converter specified the format argument is ignored.
-    pub async fn save<'b>(
+    /// Get the format to save in, checking that the output can be written in it.
+    pub(crate) fn resolve_format(&self, format: Option<Format>) -> Result<Format> {
+        let default = match self.audio_only {
+            true => DEFAULT_AUDIO_FORMAT,
+            false => DEFAULT_FORMAT,
+        };
+        let format = format.unwrap_or(default);
+        match self.get_converter(&format) {
+            Some(con) => {
+                con.verify_format(&format)?;
+                if self.embed_subtitles && !self.audio_only {
+                    format.verify_subtitles()?;
+                }
+            }
+            None if self.audio_only => {
+                return Err("Saving only audio requires a Converter.".into())
+            }
+            None if format != Format::Mp4 && format != Format::Ts => {
+                return Err(format!(
+                    "Saving as {} requires a Converter, without one the stream can only be saved as MP4 or TS.",
+                    format
+                )
+                .into())
+            }
+            None => (),
+        }
+        Ok(format)
+    }
+
+    /// Download media to file in directory.
+    /// The format defaults to MP4, or M4A when only saving audio. Without a Converter the stream can only be saved as MP4,
+    /// which it is remuxed into without re-encoding, or as TS, which saves the stream as is.
+    /// An error is returned up front if the format cannot hold the output, rather than writing a mislabelled file.
+    /// The episode metadata is written as tags, by FFMPEG with a Converter and natively without one, except to TS which has no tags.
+    ///
+    /// Episodes that fail do not stop the rest of a show from being saved. They are listed in the returned report,
+    /// along with the episodes that were saved or skipped, and an error is only returned if the url could not be looked up.
+    pub async fn save(
+        &self,
+        url: impl Into<String>,
+        out_dir: impl AsRef<str>,
+        format: Option<Format>,
+    ) -> Result<SaveReport> {
+        self.save_with_handle(url, out_dir, format, &JobHandle::new())
+            .await
+    }
+
+    /// Download media to file in directory, with the job controlled through handle. See save for how format is chosen.
+    /// If the job is cancelled, the episodes that were not saved are listed as cancelled.
+    pub async fn save_with_handle(
         &self,
         url: impl Into<String>,
         out_dir: impl AsRef<str>,
-        format: Option<Format<'b>>,
-    ) -> Result<()> {
+        format: Option<Format>,
+        handle: &JobHandle,
+    ) -> Result<SaveReport> {
         let mut url = url.into();
         Self::sanitize_url(&mut url);
-        let url_type = URLType::get(&url)?;
-        let format = format.unwrap_or(DEFAULT_FORMAT);
-        match url_type {
-            URLType::Video => self.save_ep(url, out_dir, format).await,
-            URLType::Playlist => self.save_show(url, out_dir, format).await,
+        let out_dir = out_dir.as_ref();
+        let span = tracing::info_span!("save", url = %url, out_dir);
+        async move {
+            let url_type = URLType::get(&url)?;
+            let format = self.resolve_format(format)?;
+            match url_type {
+                URLType::Video => {
+                    let jobs = Self::episode_jobs(vec![url], out_dir, &format);
+                    Ok(self.save_episodes(jobs, handle).await)
+                }
+                URLType::Playlist => self.save_show(url, out_dir, &format, handle).await,
+            }
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Save the episodes of report that failed or were cancelled again, with the job controlled through handle.
+    /// The returned report only lists the retried episodes, use SaveReport::extend to keep the rest.
+    /// Episodes are saved to the directory and in the format recorded in the report, out_dir and format are used for episodes without one.
+    pub async fn retry_failed(
+        &self,
+        report: &SaveReport,
+        out_dir: impl AsRef<str>,
+        format: Option<Format>,
+        handle: &JobHandle,
+    ) -> Result<SaveReport> {
+        let out_dir = out_dir.as_ref();
+        let unfinished = report.unfinished().collect::<Vec<_>>();
+        let span = tracing::info_span!("retry", episodes = unfinished.len(), out_dir);
+        async move {
+            let jobs = unfinished
+                .into_iter()
+                .map(|x| {
+                    let format = x.format.clone().or_else(|| format.clone());
+                    Ok(EpisodeJob {
+                        url: x.url.clone(),
+                        out_dir: x.out_dir.clone().unwrap_or_else(|| out_dir.to_owned()),
+                        format: self.resolve_format(format)?,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok(self.save_episodes(jobs, handle).await)
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Save every item of a batch with the job controlled through handle, sharing the concurrency limit of this Saver.
+    /// Items without their own output directory or format use out_dir and format.
+    /// Episodes are only saved once, even if they are listed directly and as part of a show, or in several shows.
+    /// Episodes have the same id under every show, but not always the same url, so repeats are found by id and listed as skipped.
+    /// An item that cannot be looked up is listed as failed in the report rather than stopping the batch.
+    pub async fn save_batch<T: Into<BatchItem>>(
+        &self,
+        items: impl IntoIterator<Item = T>,
+        out_dir: impl AsRef<str>,
+        format: Option<Format>,
+        handle: &JobHandle,
+    ) -> Result<SaveReport> {
+        let out_dir = out_dir.as_ref();
+        let items = items
+            .into_iter()
+            .map(Into::into)
+            .collect::<Vec<BatchItem>>();
+        let span = tracing::info_span!("batch", items = items.len(), out_dir);
+        async move {
+            let mut report = SaveReport::default();
+            let mut jobs = vec![];
+            for mut item in items {
+                Self::sanitize_url(&mut item.url);
+                let url = item.url.clone();
+                let (item_out_dir, item_format) =
+                    match self.batch_target(&item, out_dir, format.as_ref()) {
+                        Ok(x) => x,
+                        Err(e) => {
+                            tracing::warn!(url = %url, error = %e, "could not resolve batch item");
+                            report.episodes.push(EpisodeReport {
+                                url,
+                                episode: None,
+                                out_dir: None,
+                                format: None,
+                                outcome: EpisodeOutcome::from_error(e),
+                            });
+                            continue;
+                        }
+                    };
+                let ep_urls = match self.batch_urls(item.url).await {
+                    Ok(x) => x,
+                    Err(e) => {
+                        tracing::warn!(url = %url, error = %e, "could not look up batch item");
+                        report.episodes.push(EpisodeReport {
+                            url,
+                            episode: None,
+                            out_dir: Some(item_out_dir),
+                            format: Some(item_format),
+                            outcome: EpisodeOutcome::from_error(e),
+                        });
+                        continue;
+                    }
+                };
+                jobs.extend(Self::episode_jobs(ep_urls, &item_out_dir, &item_format));
+            }
+            report.extend(self.save_episodes(jobs, handle).await);
+            Ok(report)
         }
+        .instrument(span)
+        .await
+    }
+
+    /// Save every url in the url list at path, see save_batch and parse_url_list.
+    pub async fn save_batch_file(
+        &self,
+        path: impl AsRef<Path>,
+        out_dir: impl AsRef<str>,
+        format: Option<Format>,
+        handle: &JobHandle,
+    ) -> Result<SaveReport> {
+        let items = read_url_list(path).await?;
+        self.save_batch(items, out_dir, format, handle).await
     }
 }
@@ -0,0 +1,265 @@
+use crate::batch::{verify_out_dir, BatchItem};
+use crate::error::Result;
+use crate::format::Format;
+use crate::job::JobHandle;
+use crate::models::{DownloadEvent, URLType};
+use crate::queue::{JobQueue, QueueJobId, QueueJobStatus};
+use crate::saver::Saver;
+use axum::extract::{Path as UrlPath, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event as SseEvent, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use futures::{Stream, StreamExt};
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::net::{TcpListener, ToSocketAddrs};
+
+struct ServerState {
+    saver: Saver,
+    queue: JobQueue,
+    handle: JobHandle,
+}
+
+/// A local HTTP API for queueing and monitoring downloads, which runs the jobs of a JobQueue with a Saver.
+///
+/// - `POST /jobs` with `{"url": ..., "out_dir": ..., "format": ..., "priority": ...}` queues a url. Only the url is required,
+///   and out_dir must be relative to the output directory of the server.
+/// - `GET /jobs` lists every job, and `GET /jobs/{id}` gets a job with the report of its last attempt.
+/// - `DELETE /jobs/{id}` cancels a job.
+/// - `GET /events` is a stream of server-sent events, with an `episode` event per download event and a `job` event per job update.
+///
+/// There is no authentication, so only serve on networks where everyone may queue downloads.
+pub struct Server {
+    saver: Saver,
+    queue: JobQueue,
+    out_dir: String,
+}
+
+impl Server {
+    /// Construct a server that saves jobs to out_dir.
+    pub fn new(saver: Saver, queue: JobQueue, out_dir: impl Into<String>) -> Self {
+        Server {
+            saver,
+            queue,
+            out_dir: out_dir.into(),
+        }
+    }
+
+    /// Serve the API on addr and run queued jobs until handle is cancelled.
+    /// Cancelling stops the running job, which stays queued to resume when the server is started again.
+    /// If the server or the runner fails, the other is stopped as well and the error is returned.
+    pub async fn serve(self, addr: impl ToSocketAddrs, handle: &JobHandle) -> Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        tracing::info!(addr = %listener.local_addr()?, "serving");
+        // Cancelled when handle is, or when the server or the runner ends, which then stops the other.
+        let stop = JobHandle::new();
+        let state = Arc::new(ServerState {
+            saver: self.saver.clone(),
+            queue: self.queue.clone(),
+            handle: stop.clone(),
+        });
+        let app = Router::new()
+            .route("/jobs", get(list_jobs).post(enqueue))
+            .route("/jobs/:id", get(get_job).delete(cancel_job))
+            .route("/events", get(events))
+            .with_state(state);
+        let shutdown = stop.clone();
+        let server = async {
+            let served = axum::serve(listener, app)
+                .with_graceful_shutdown(async move { shutdown.cancelled().await })
+                .await;
+            if let Err(e) = &served {
+                tracing::error!(error = %e, "server failed");
+            }
+            stop.cancel();
+            served
+        };
+        let runner = async {
+            let ran = self.queue.run(&self.saver, &self.out_dir, &stop).await;
+            if let Err(e) = &ran {
+                tracing::error!(error = %e, "queue runner failed");
+            }
+            stop.cancel();
+            ran
+        };
+        let forward = async {
+            tokio::select! {
+                _ = handle.forward_to(&stop) => (),
+                _ = stop.cancelled() => (),
+            }
+        };
+        let (served, ran, _) = tokio::join!(server, runner, forward);
+        served?;
+        ran
+    }
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(json!({ "error": message.into() }))).into_response()
+}
+
+/// Get the output directory of a request, which must stay inside the output directory of the server.
+fn parse_out_dir(value: &Value) -> std::result::Result<Option<String>, String> {
+    let out_dir = match value {
+        Value::Null => return Ok(None),
+        Value::String(x) => x,
+        _ => return Err("out_dir must be a string.".to_owned()),
+    };
+    verify_out_dir(out_dir).map_err(|e| e.to_string())?;
+    Ok(Some(out_dir.clone()))
+}
+
+fn parse_priority(value: &Value) -> std::result::Result<i32, String> {
+    match value {
+        Value::Null => Ok(0),
+        x => x
+            .as_i64()
+            .and_then(|x| i32::try_from(x).ok())
+            .ok_or_else(|| {
+                format!(
+                    "priority must be an integer from {} to {}.",
+                    i32::MIN,
+                    i32::MAX
+                )
+            }),
+    }
+}
+
+async fn enqueue(State(state): State<Arc<ServerState>>, Json(body): Json<Value>) -> Response {
+    let url = match body["url"].as_str() {
+        Some(x) => x.trim().to_owned(),
+        None => return error(StatusCode::BAD_REQUEST, "url is required."),
+    };
+    if let Err(e) = URLType::get(&url) {
+        return error(StatusCode::BAD_REQUEST, e.to_string());
+    }
+    let mut item = BatchItem::new(url);
+    item.out_dir = match parse_out_dir(&body["out_dir"]) {
+        Ok(x) => x,
+        Err(e) => return error(StatusCode::BAD_REQUEST, e),
+    };
+    if let Some(format) = body["format"].as_str() {
+        // Check the format now, rather than failing the job later.
+        match state.saver.resolve_format(Some(Format::new(format))) {
+            Ok(x) => item.format = Some(x),
+            Err(e) => return error(StatusCode::BAD_REQUEST, e.to_string()),
+        }
+    }
+    let priority = match parse_priority(&body["priority"]) {
+        Ok(x) => x,
+        Err(e) => return error(StatusCode::BAD_REQUEST, e),
+    };
+    let id = match state.queue.enqueue(item, priority).await {
+        Ok(x) => x,
+        Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    };
+    match state.queue.get(id).await {
+        Some(job) => (StatusCode::CREATED, Json(job.to_json())).into_response(),
+        None => error(StatusCode::NOT_FOUND, format!("There is no job {}.", id)),
+    }
+}
+
+async fn list_jobs(State(state): State<Arc<ServerState>>) -> Response {
+    let jobs = state
+        .queue
+        .jobs()
+        .await
+        .iter()
+        .map(|x| x.to_json())
+        .collect::<Vec<_>>();
+    Json(Value::Array(jobs)).into_response()
+}
+
+async fn get_job(
+    State(state): State<Arc<ServerState>>,
+    UrlPath(id): UrlPath<QueueJobId>,
+) -> Response {
+    match state.queue.get(id).await {
+        Some(job) => Json(job.to_json()).into_response(),
+        None => error(StatusCode::NOT_FOUND, format!("There is no job {}.", id)),
+    }
+}
+
+async fn cancel_job(
+    State(state): State<Arc<ServerState>>,
+    UrlPath(id): UrlPath<QueueJobId>,
+) -> Response {
+    let status = match state.queue.get(id).await {
+        Some(job) => job.status,
+        None => return error(StatusCode::NOT_FOUND, format!("There is no job {}.", id)),
+    };
+    if matches!(status, QueueJobStatus::Done | QueueJobStatus::Failed) {
+        return error(
+            StatusCode::CONFLICT,
+            format!("Job {} has already finished.", id),
+        );
+    }
+    match state.queue.cancel(id).await {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+fn episode_event_json(event: &DownloadEvent) -> Value {
+    let episode = event.episode();
+    let details = match event {
+        DownloadEvent::Resolved { .. } => json!({}),
+        DownloadEvent::Started { .. } => json!({}),
+        DownloadEvent::Progress { progress, .. } => json!({
+            "bytes": progress.bytes,
+            "segments_done": progress.segments_done,
+            "segments_total": progress.segments_total,
+            "speed": progress.speed,
+            "estimated_size": progress.estimated_size,
+            "eta_secs": progress.eta.map(|x| x.as_secs_f64()),
+        }),
+        DownloadEvent::ConvertStarted { .. } => json!({}),
+        DownloadEvent::ConvertProgress { progress, .. } => json!({
+            "out_time_secs": progress.out_time.as_secs_f64(),
+            "total_size": progress.total_size,
+            "speed": progress.speed,
+            "percentage": progress.percentage,
+            "done": progress.done,
+        }),
+        DownloadEvent::Finished { path, bytes, .. } => json!({
+            "path": path.as_ref().map(|x| x.to_string_lossy()),
+            "bytes": bytes,
+        }),
+        DownloadEvent::Skipped { reason, .. } => json!({ "reason": reason }),
+        DownloadEvent::Cancelled { .. } => json!({}),
+        DownloadEvent::Failed { error, .. } => json!({ "error": error }),
+    };
+    json!({
+        "type": event.kind(),
+        "episode": {
+            "id": episode.id,
+            "name": episode.name,
+            "title": episode.title(),
+        },
+        "details": details,
+    })
+}
+
+async fn events(
+    State(state): State<Arc<ServerState>>,
+) -> Sse<impl Stream<Item = std::result::Result<SseEvent, Infallible>>> {
+    let episodes = state.saver.event_stream().map(|x| {
+        SseEvent::default()
+            .event("episode")
+            .data(episode_event_json(&x).to_string())
+    });
+    let jobs = state.queue.update_stream().await.map(|x| {
+        SseEvent::default()
+            .event("job")
+            .data(x.to_json().to_string())
+    });
+    // Streams end when the server stops, so they do not hold up its shutdown.
+    let handle = state.handle.clone();
+    let stream = futures::stream::select(episodes, jobs)
+        .take_until(async move { handle.cancelled().await })
+        .map(Ok);
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
@@ -0,0 +1,447 @@
+use crate::error::Result;
+use crate::filename::{legalize_filename_with, FilenameOptions};
+use crate::format::Format;
+use crate::models::episode::{EpisodeInfo, NETWORK};
+use std::fmt::{self, Display, Formatter};
+use std::iter::Peekable;
+use std::path::{Component, PathBuf};
+use std::str::{Chars, FromStr};
+
+/// The template used when none is given, which names files after the name in the episode url.
+pub const DEFAULT_TEMPLATE: &str = "{name}.{ext}";
+
+/// The layout Plex, Jellyfin and Kodi expect, with a directory per show and season.
+pub const MEDIA_SERVER_TEMPLATE: &str =
+    "{show|Other}/[Season {season:02}/]{show|Other}[ - S{season:02}E{episode:02}] - {title}.{ext}";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Show,
+    Title,
+    Name,
+    Id,
+    Season,
+    Episode,
+    Description,
+    AirDate,
+    Year,
+    Genre,
+    Network,
+    Ext,
+}
+
+enum Value {
+    Text(String),
+    Number(u32),
+}
+
+impl Field {
+    const ALL: [Field; 12] = [
+        Field::Show,
+        Field::Title,
+        Field::Name,
+        Field::Id,
+        Field::Season,
+        Field::Episode,
+        Field::Description,
+        Field::AirDate,
+        Field::Year,
+        Field::Genre,
+        Field::Network,
+        Field::Ext,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Field::Show => "show",
+            Field::Title => "title",
+            Field::Name => "name",
+            Field::Id => "id",
+            Field::Season => "season",
+            Field::Episode => "episode",
+            Field::Description => "description",
+            Field::AirDate => "air_date",
+            Field::Year => "year",
+            Field::Genre => "genre",
+            Field::Network => "network",
+            Field::Ext => "ext",
+        }
+    }
+
+    fn is_number(self) -> bool {
+        matches!(self, Field::Season | Field::Episode | Field::Year)
+    }
+
+    fn value(self, episode: &EpisodeInfo, format: &Format) -> Option<Value> {
+        let meta = &episode.metadata;
+        let text = |x: &Option<String>| x.clone().map(Value::Text);
+        match self {
+            Field::Show => text(&meta.show),
+            Field::Title => Some(Value::Text(episode.title().to_owned())),
+            Field::Name => Some(Value::Text(episode.name.clone())),
+            Field::Id => Some(Value::Text(episode.id.clone())),
+            Field::Season => meta.season.map(Value::Number),
+            Field::Episode => meta.episode.map(Value::Number),
+            Field::Description => text(&meta.description),
+            Field::AirDate => text(&meta.air_date),
+            Field::Year => meta
+                .air_date
+                .as_ref()
+                .and_then(|x| x.get(..4)?.parse().ok())
+                .map(Value::Number),
+            Field::Genre => Some(meta.genres.join(", "))
+                .filter(|x| !x.is_empty())
+                .map(Value::Text),
+            Field::Network => Some(Value::Text(NETWORK.to_owned())),
+            Field::Ext => Some(Value::Text(
+                format.get_extension().trim_start_matches('.').to_owned(),
+            )),
+        }
+    }
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Remove directory separators from a field value, so it cannot add directories.
+fn strip_separators(value: &str) -> String {
+    value.replace(['/', '\\'], "")
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Separator,
+    Field {
+        field: Field,
+        width: usize,
+        default: Option<String>,
+    },
+    /// Left out entirely if a field in it is unknown.
+    Optional(Vec<Part>),
+}
+
+/// A template for the path of saved episodes, relative to the output directory.
+///
+/// Fields are written as {field}, and are show, title, name, id, season, episode, description, air_date, year, genre, network and ext.
+/// Numbers can be padded with zeros as {season:02}. A field can fall back to a default as {show|Unknown show}.
+/// Sections in square brackets are left out if a field in them is unknown, such as [Season {season:02}/].
+/// A / separates directories, and {{, }}, [[ and ]] write the brackets themselves. Separators in field values are removed.
+/// An error is returned when saving an episode where a field outside a section is unknown and has no default.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathTemplate {
+    parts: Vec<Part>,
+}
+
+impl PathTemplate {
+    pub fn new(template: &str) -> Result<Self> {
+        let mut chars = template.chars().peekable();
+        let parts = Self::parse_parts(&mut chars, false)?;
+        Ok(PathTemplate { parts })
+    }
+
+    /// Get the template of MEDIA_SERVER_TEMPLATE.
+    pub fn media_server() -> Self {
+        Self::new(MEDIA_SERVER_TEMPLATE).expect("The media server template is valid.")
+    }
+
+    fn parse_parts(chars: &mut Peekable<Chars>, nested: bool) -> Result<Vec<Part>> {
+        let mut parts = vec![];
+        let mut text = String::new();
+        let flush = |text: &mut String, parts: &mut Vec<Part>| {
+            if !text.is_empty() {
+                parts.push(Part::Text(std::mem::take(text)));
+            }
+        };
+        while let Some(ch) = chars.next() {
+            match ch {
+                '{' | '}' | '[' | ']' if chars.peek() == Some(&ch) => {
+                    chars.next();
+                    text.push(ch);
+                }
+                '{' => {
+                    flush(&mut text, &mut parts);
+                    parts.push(Self::parse_field(chars)?);
+                }
+                '[' => {
+                    flush(&mut text, &mut parts);
+                    parts.push(Part::Optional(Self::parse_parts(chars, true)?));
+                }
+                ']' if nested => {
+                    flush(&mut text, &mut parts);
+                    return Ok(parts);
+                }
+                '/' | '\\' => {
+                    flush(&mut text, &mut parts);
+                    parts.push(Part::Separator);
+                }
+                '}' | ']' => return Err(format!("Unmatched '{}' in template.", ch).into()),
+                x => text.push(x),
+            }
+        }
+        if nested {
+            return Err("Unclosed '[' in template.".into());
+        }
+        flush(&mut text, &mut parts);
+        Ok(parts)
+    }
+
+    fn parse_field(chars: &mut Peekable<Chars>) -> Result<Part> {
+        let mut content = String::new();
+        loop {
+            match chars.next() {
+                Some('}') => break,
+                Some(x) => content.push(x),
+                None => return Err("Unclosed '{' in template.".into()),
+            }
+        }
+        let (spec, default) = match content.split_once('|') {
+            Some((spec, default)) => (spec, Some(default.to_owned())),
+            None => (content.as_str(), None),
+        };
+        let (name, width) = spec.split_once(':').unwrap_or((spec, ""));
+        let field = Field::ALL
+            .into_iter()
+            .find(|x| x.name() == name.trim())
+            .ok_or_else(|| format!("Unknown field '{}' in template.", name))?;
+        let width = match width {
+            "" => 0,
+            _ if !field.is_number() => {
+                return Err(
+                    format!("Only numbers can be padded, {} is not a number.", field).into(),
+                )
+            }
+            x => x
+                .parse()
+                .map_err(|_| format!("Invalid width '{}' of {} in template.", x, field))?,
+        };
+        Ok(Part::Field {
+            field,
+            width,
+            default,
+        })
+    }
+
+    /// Render parts into out, returning the first unknown field without a default.
+    fn render_parts(
+        parts: &[Part],
+        episode: &EpisodeInfo,
+        format: &Format,
+        out: &mut String,
+    ) -> std::result::Result<(), Field> {
+        for part in parts {
+            match part {
+                Part::Text(x) => out.push_str(x),
+                Part::Separator => out.push('/'),
+                Part::Field {
+                    field,
+                    width,
+                    default,
+                } => match (field.value(episode, format), default) {
+                    (Some(Value::Number(x)), _) => out.push_str(&format!("{:0width$}", x)),
+                    (Some(Value::Text(x)), _) => out.push_str(&strip_separators(&x)),
+                    (None, Some(default)) => out.push_str(&strip_separators(default)),
+                    (None, None) => return Err(*field),
+                },
+                Part::Optional(parts) => {
+                    let mut section = String::new();
+                    if Self::render_parts(parts, episode, format, &mut section).is_ok() {
+                        out.push_str(&section);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the path of episode saved in format, relative to the output directory.
+    /// Every directory and the filename are made legal with options, so the path never leaves the output directory.
+    /// If options cap the length of paths, the filename is shortened to fit.
+    pub fn render(
+        &self,
+        episode: &EpisodeInfo,
+        format: &Format,
+        options: &FilenameOptions,
+    ) -> Result<PathBuf> {
+        let mut rendered = String::new();
+        if let Err(field) = Self::render_parts(&self.parts, episode, format, &mut rendered) {
+            return Err(format!(
+                "The {} of episode {} is unknown, give it a default in the template or put it in a [section].",
+                field, episode.id
+            )
+            .into());
+        }
+        let mut names = rendered
+            .split('/')
+            .filter(|x| !x.trim().is_empty())
+            .map(|x| legalize_filename_with(x, options))
+            .collect::<Vec<_>>();
+        if let (Some(max), Some((file, dirs))) = (options.max_path_bytes, names.split_last_mut()) {
+            // Each directory is followed by a separator.
+            let dirs_len = dirs.iter().map(|x| x.len() + 1).sum::<usize>();
+            let room = max
+                .checked_sub(dirs_len)
+                .filter(|x| *x > 0)
+                .ok_or_else(|| {
+                    format!(
+                        "The directories of {} are longer than the {} bytes paths are capped at.",
+                        rendered, max
+                    )
+                })?;
+            if file.len() > room {
+                let options = FilenameOptions {
+                    max_bytes: room,
+                    ..options.clone()
+                };
+                *file = legalize_filename_with(&*file, &options);
+            }
+        }
+        let path = names.into_iter().collect::<PathBuf>();
+        if path.as_os_str().is_empty() {
+            return Err("Template produced an empty path.".into());
+        }
+        if !path.components().all(|x| matches!(x, Component::Normal(_))) {
+            return Err(format!(
+                "Template produced the path {}, which leaves the output directory.",
+                path.display()
+            )
+            .into());
+        }
+        Ok(path)
+    }
+}
+
+impl FromStr for PathTemplate {
+    type Err = Box<dyn std::error::Error + Send + Sync>;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::new(s)
+    }
+}
+
+impl Default for PathTemplate {
+    fn default() -> Self {
+        Self::new(DEFAULT_TEMPLATE).expect("The default template is valid.")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filename::TargetOs;
+    use crate::models::episode::EpisodeMetadata;
+    use std::path::Path;
+
+    fn episode() -> EpisodeInfo {
+        EpisodeInfo {
+            name: "bonderoeven-2024-1".to_owned(),
+            id: "00122414010".to_owned(),
+            metadata: EpisodeMetadata {
+                title: Some("Gården vågner".to_owned()),
+                show: Some("Bonderøven".to_owned()),
+                season: Some(3),
+                episode: Some(7),
+                air_date: Some("2024-03-01".to_owned()),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn options() -> FilenameOptions {
+        FilenameOptions {
+            target: TargetOs::Unix,
+            ..Default::default()
+        }
+    }
+
+    fn render(template: &str, episode: &EpisodeInfo) -> Result<PathBuf> {
+        PathTemplate::new(template)?.render(episode, &Format::Mp4, &options())
+    }
+
+    #[test]
+    fn new_rejects_invalid_templates() {
+        for template in [
+            "{name",
+            "name}",
+            "[{name}",
+            "{name}]",
+            "{unknown}",
+            "{title:02}",
+            "{season:x}",
+        ] {
+            assert!(PathTemplate::new(template).is_err(), "{}", template);
+        }
+        assert!(PathTemplate::new(DEFAULT_TEMPLATE).is_ok());
+        assert!(PathTemplate::new(MEDIA_SERVER_TEMPLATE).is_ok());
+    }
+
+    #[test]
+    fn render_fills_in_fields() {
+        let ep = episode();
+        assert_eq!(
+            render(DEFAULT_TEMPLATE, &ep).unwrap(),
+            Path::new("bonderoeven-2024-1.mp4")
+        );
+        assert_eq!(
+            render("{year}/{network} {id} {{{season:03}}} [[x]].{ext}", &ep).unwrap(),
+            Path::new("2024/DR 00122414010 {003} [x].mp4")
+        );
+        assert_eq!(
+            render(MEDIA_SERVER_TEMPLATE, &ep).unwrap(),
+            Path::new("Bonderøven/Season 03/Bonderøven - S03E07 - Gården vågner.mp4")
+        );
+    }
+
+    #[test]
+    fn render_leaves_out_sections_and_uses_defaults() {
+        let mut ep = episode();
+        ep.metadata = EpisodeMetadata::default();
+        assert_eq!(
+            render(MEDIA_SERVER_TEMPLATE, &ep).unwrap(),
+            Path::new("Other/Other - bonderoeven-2024-1.mp4")
+        );
+        let err = render("{show}/{name}", &ep).unwrap_err();
+        assert!(err.to_string().contains("show"), "{}", err);
+    }
+
+    #[test]
+    fn render_stays_in_the_output_directory() {
+        let mut ep = episode();
+        ep.metadata.show = Some("../..".to_owned());
+        ep.metadata.title = Some("/etc/passwd".to_owned());
+        let path = render("{show}/{title}", &ep).unwrap();
+        assert!(path.components().all(|x| matches!(x, Component::Normal(_))));
+        assert_eq!(path, Path::new("_/etcpasswd"));
+        assert!(render("[{description}]", &ep).is_err());
+        assert_eq!(
+            render("../{name}", &ep).unwrap(),
+            Path::new("_/bonderoeven-2024-1")
+        );
+    }
+
+    #[test]
+    fn render_caps_the_path_length() {
+        let ep = episode();
+        let template = PathTemplate::new(MEDIA_SERVER_TEMPLATE).unwrap();
+        let capped = |max| {
+            let options = FilenameOptions {
+                max_path_bytes: Some(max),
+                ..options()
+            };
+            template.render(&ep, &Format::Mp4, &options)
+        };
+        let full = capped(1000).unwrap();
+        let max = full.as_os_str().len() - 10;
+        let path = capped(max).unwrap();
+        assert!(path.as_os_str().len() <= max);
+        assert_eq!(path.parent(), full.parent());
+        assert_eq!(path.extension().unwrap(), "mp4");
+        // Nothing is left of the filename if the cap only fits the directories and their separators.
+        let dirs = full.parent().unwrap().as_os_str().len() + 1;
+        assert!(capped(dirs).is_err());
+        assert_eq!(capped(dirs + 1).unwrap().parent(), full.parent());
+    }
+}
@@ -1,4 +1,5 @@
 use crate::error::GenericError;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn remove_newline(string: &str) -> &str {
     let mut end = string.len();
@@ -61,9 +62,119 @@ pub fn rfind_char(
     Err(format!("Could not find {} in {}", to_find, string).into())
 }
 
-pub fn legalize_filename(name: impl Into<String>) -> String {
-    const ILLEGAL_CHARS: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
-    let mut name = name.into();
-    name.retain(|x| !ILLEGAL_CHARS.iter().any(|a| x == *a));
-    name
+/// Replace the values of query parameters known to hold credentials, so the url can be logged.
+/// Names are matched exactly, ignoring case.
+pub fn redact_url(url: &str) -> String {
+    const SECRET_PARAMS: &[&str] = &[
+        "token",
+        "__token__",
+        "access_token",
+        "auth",
+        "authorization",
+        "key",
+        "api_key",
+        "apikey",
+        "sig",
+        "signature",
+        "hmac",
+        "policy",
+        "hdnts",
+        "hdnea",
+        "x-amz-credential",
+        "x-amz-security-token",
+        "x-amz-signature",
+    ];
+    let mut parsed = match reqwest::Url::parse(url) {
+        Ok(x) => x,
+        Err(_) => return url.to_owned(),
+    };
+    if parsed.query().is_none() {
+        return url.to_owned();
+    }
+    let pairs = parsed
+        .query_pairs()
+        .map(
+            |(k, v)| match SECRET_PARAMS.iter().any(|x| k.eq_ignore_ascii_case(x)) {
+                true => (k.into_owned(), "REDACTED".to_owned()),
+                false => (k.into_owned(), v.into_owned()),
+            },
+        )
+        .collect::<Vec<_>>();
+    parsed.query_pairs_mut().clear().extend_pairs(pairs);
+    parsed.to_string()
+}
+
+/// Guess the MIME type of an image from its first bytes, defaulting to JPEG.
+pub fn image_mime_type(data: &[u8]) -> &'static str {
+    match data {
+        [0x89, b'P', b'N', b'G', ..] => "image/png",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "image/webp",
+        [b'G', b'I', b'F', ..] => "image/gif",
+        _ => "image/jpeg",
+    }
+}
+
+/// Format time as an ISO 8601 UTC timestamp, such as "2024-03-01T12:30:00Z".
+pub fn format_utc_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or(0);
+    let time_of_day = secs % 86400;
+    // Civil date from days since 1970-01-01, after Howard Hinnant's civil_from_days.
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time_of_day / 3600,
+        time_of_day % 3600 / 60,
+        time_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_url_redacts_credentials_by_name() {
+        assert_eq!(
+            redact_url(
+                "https://cdn.example.com/index.m3u8?hdnts=exp~hmac&Token=abc&monkey=1&keyframe=2"
+            ),
+            "https://cdn.example.com/index.m3u8?hdnts=REDACTED&Token=REDACTED&monkey=1&keyframe=2"
+        );
+        assert_eq!(
+            redact_url("https://example.com/a?X-Amz-Signature=abc&sig=def"),
+            "https://example.com/a?X-Amz-Signature=REDACTED&sig=REDACTED"
+        );
+    }
+
+    #[test]
+    fn redact_url_keeps_urls_without_query() {
+        assert_eq!(redact_url("https://example.com/a"), "https://example.com/a");
+        assert_eq!(redact_url("not a url?token=abc"), "not a url?token=abc");
+    }
+
+    #[test]
+    fn format_utc_timestamp_formats_civil_dates() {
+        use std::time::Duration;
+        let at = |secs| format_utc_timestamp(UNIX_EPOCH + Duration::from_secs(secs));
+        assert_eq!(at(0), "1970-01-01T00:00:00Z");
+        assert_eq!(at(1709209845), "2024-02-29T12:30:45Z");
+        // 2100 is not a leap year, but 2400 is.
+        assert_eq!(at(4107542399), "2100-02-28T23:59:59Z");
+        assert_eq!(at(4107542400), "2100-03-01T00:00:00Z");
+        assert_eq!(at(13574563200), "2400-02-29T00:00:00Z");
+    }
 }